@@ -0,0 +1,232 @@
+use bevy::prelude::{Added, Local, Query, Res, ResMut};
+
+use crate::ceiling::HardCeiling;
+use crate::column_stats::ColumnStats;
+use crate::{fits_at, BufferedInput, FallingSegment, FullGrid, Tile, COLUMNS, ROWS};
+
+/// Whether the falling pieces are steered by the AI rather than the player
+pub(crate) struct AiControlled(pub bool);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Placement {
+    /// How many times to rotate the piece clockwise
+    pub rotations: usize,
+    /// Where the piece's focal point should end up
+    pub x: i8,
+}
+
+fn rotated(shape: &[FallingSegment], rotations: usize) -> Vec<FallingSegment> {
+    shape
+        .iter()
+        .map(|&segment| (0..rotations).fold(segment, |s, _| s.rotate_clockwise()))
+        .collect()
+}
+
+fn tiles_at(shape: &[FallingSegment], x: i8, y: i8) -> impl Iterator<Item = Tile> + '_ {
+    shape.iter().map(move |segment| Tile {
+        x: x + segment.x_offset,
+        y: y + segment.y_offset,
+    })
+}
+
+/// Scores the board left behind by a placement, higher being better
+fn evaluate(mut full_grid: FullGrid, placed: impl Iterator<Item = Tile>) -> f32 {
+    for Tile { x, y } in placed {
+        match full_grid.0.get_mut(usize::try_from(y).unwrap()) {
+            Some(row) => row[usize::try_from(x).unwrap()] = true,
+            None => return f32::NEG_INFINITY,
+        }
+    }
+    let mut lines = 0;
+    for y in (0..ROWS).rev() {
+        if full_grid.0[y] == [true; COLUMNS] {
+            full_grid.0[y..].rotate_left(1);
+            *full_grid.0.last_mut().unwrap() = [false; COLUMNS];
+            lines += 1;
+        }
+    }
+    if full_grid.0[ROWS..]
+        .iter()
+        .any(|row| *row != [false; COLUMNS])
+    {
+        return f32::NEG_INFINITY;
+    }
+    let stats = ColumnStats::of(&full_grid);
+    let aggregate_height: usize = stats.heights.iter().sum();
+    -0.51 * aggregate_height as f32 + 0.76 * lines as f32
+        - 0.36 * stats.holes as f32
+        - 0.18 * stats.bumpiness as f32
+}
+
+/// Finds the best rotation and column to drop a piece into,
+/// given its current shape and focal point
+pub(crate) fn best_placement(
+    shape: &[FallingSegment],
+    focal: Tile,
+    hard_ceiling: bool,
+    full_grid: &FullGrid,
+) -> Option<Placement> {
+    let mut best: Option<(f32, Placement)> = None;
+    for rotations in 0..4 {
+        let shape = rotated(shape, rotations);
+        for x in 0..COLUMNS as i8 {
+            if !fits_at(tiles_at(&shape, x, focal.y), hard_ceiling, full_grid) {
+                continue;
+            }
+            let mut y = focal.y;
+            while fits_at(tiles_at(&shape, x, y - 1), hard_ceiling, full_grid) {
+                y -= 1;
+            }
+            let score = evaluate(full_grid.clone(), tiles_at(&shape, x, y));
+            let better = match best {
+                Some((best_score, _)) => score > best_score,
+                None => true,
+            };
+            if better {
+                best = Some((score, Placement { rotations, x }));
+            }
+        }
+    }
+    best.map(|(_, placement)| placement)
+}
+
+/// Where the AI is steering the current piece
+pub(crate) struct Plan {
+    shape: Vec<FallingSegment>,
+    x: i8,
+    /// The piece's shape when it was last asked to turn, to tell if the turn was blocked
+    turned_from: Option<Vec<FallingSegment>>,
+}
+
+/// Steers the falling piece towards the best placement one move per frame,
+/// feeding the moves through the same buffer as held-over player input
+pub(crate) fn ai_player(
+    ai_controlled: Res<AiControlled>,
+    full_grid: Res<FullGrid>,
+    hard_ceiling: Res<HardCeiling>,
+    segments: Query<(&Tile, &FallingSegment)>,
+    new_segments: Query<(), Added<FallingSegment>>,
+    mut buffered: ResMut<BufferedInput>,
+    mut plan: Local<Option<Plan>>,
+) {
+    if !ai_controlled.0 || !new_segments.is_empty() {
+        *plan = None;
+        return;
+    }
+    let (tile, segment) = match segments.iter().next() {
+        Some(first) => first,
+        None => {
+            *plan = None;
+            return;
+        }
+    };
+    let focal = Tile {
+        x: tile.x - segment.x_offset,
+        y: tile.y - segment.y_offset,
+    };
+    let shape: Vec<FallingSegment> = segments.iter().map(|(_, segment)| *segment).collect();
+
+    if plan.is_none() {
+        *plan = best_placement(&shape, focal, hard_ceiling.0, &full_grid).map(|placement| Plan {
+            shape: rotated(&shape, placement.rotations),
+            x: placement.x,
+            turned_from: None,
+        });
+    }
+    let plan = match &mut *plan {
+        Some(plan) => plan,
+        None => return,
+    };
+    if !shape.iter().all(|segment| plan.shape.contains(segment)) {
+        if plan.turned_from.as_ref() == Some(&shape) {
+            // There are no kicks, so make room by moving away from the wall first
+            plan.turned_from = None;
+            if focal.x < COLUMNS as i8 / 2 {
+                buffered.right = true;
+            } else {
+                buffered.left = true;
+            }
+        } else {
+            plan.turned_from = Some(shape);
+            buffered.x = true;
+        }
+    } else if focal.x < plan.x {
+        buffered.right = true;
+    } else if focal.x > plan.x {
+        buffered.left = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::schedule::{ParallelSystemDescriptorCoercion, Stage, SystemStage};
+    use bevy::prelude::{Input, KeyCode};
+
+    use super::*;
+    use crate::handle_input;
+    use crate::testing::{game, piece_cells, press, run, shift_piece, spawn_piece};
+    use crate::tetrominoes::PieceKind;
+
+    /// A board with its bottom four rows filled apart from the right column
+    fn well() -> FullGrid {
+        let mut full_grid = FullGrid::empty();
+        for row in &mut full_grid.0[..4] {
+            *row = [true; COLUMNS];
+            row[COLUMNS - 1] = false;
+        }
+        full_grid
+    }
+
+    #[test]
+    fn filling_the_well_beats_stacking_on_top() {
+        let full_grid = well();
+        let shape = PieceKind::I.tetromino().shape;
+        let standing = rotated(shape, 1);
+        // The focal point that puts the standing I's cells in the well
+        let x = COLUMNS as i8 - 1 - standing[0].x_offset;
+        let into_well = evaluate(full_grid.clone(), tiles_at(&standing, x, 1));
+        let on_top = evaluate(full_grid, tiles_at(shape, 4, 3));
+        assert!(into_well > on_top);
+    }
+
+    #[test]
+    fn placements_above_the_board_are_ruled_out() {
+        let shape = PieceKind::O.tetromino().shape;
+        let score = evaluate(FullGrid::empty(), tiles_at(shape, 4, ROWS as i8));
+        assert_eq!(score, f32::NEG_INFINITY);
+    }
+
+    #[test]
+    fn drops_an_i_into_the_well() {
+        let full_grid = well();
+        let shape = PieceKind::I.tetromino().shape;
+        let placement = best_placement(shape, Tile { x: 4, y: 18 }, false, &full_grid).unwrap();
+        let placed = rotated(shape, placement.rotations);
+        assert!(tiles_at(&placed, placement.x, 0).all(|tile| tile.x == COLUMNS as i8 - 1));
+    }
+
+    #[test]
+    fn steps_away_from_the_wall_when_it_cant_turn() {
+        let mut world = game();
+        world.insert_resource(AiControlled(true));
+        spawn_piece(&mut world, PieceKind::I);
+        // Stand the I up against the left wall, where it can't turn back flat
+        press(&mut world, &[KeyCode::X]);
+        run(&mut world, handle_input);
+        let left_edge = piece_cells(&mut world)[0].0;
+        shift_piece(&mut world, -left_edge, 0);
+        let mut input = world.resource_mut::<Input<KeyCode>>();
+        input.release(KeyCode::X);
+        input.clear();
+
+        let mut stage = SystemStage::single_threaded();
+        stage.add_system(ai_player.before(handle_input));
+        stage.add_system(handle_input);
+        for _ in 0..30 {
+            stage.run(&mut world);
+        }
+        // An I lies flat on an empty board
+        let cells = piece_cells(&mut world);
+        assert!(cells.iter().all(|&(_, y)| y == cells[0].1));
+    }
+}
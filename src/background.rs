@@ -0,0 +1,101 @@
+use bevy::asset::LoadState;
+use bevy::log::warn;
+use bevy::prelude::{
+    AssetServer, Assets, ClearColor, Color, Commands, Component, DespawnRecursiveExt, Entity,
+    Handle, Image, Query, Res, ResMut, Transform, Vec2, With,
+};
+use bevy::sprite::{Sprite, SpriteBundle};
+use bevy::window::Windows;
+
+use crate::{BACKGROUND, Z_BACKGROUND};
+
+/// What's drawn behind the board
+pub(crate) enum Background {
+    SolidColor(Color),
+    /// The path of an image in the assets folder, scaled to cover the window
+    Image(String),
+}
+
+impl Background {
+    /// Uses the image named by `TETRIS_BACKGROUND`, if set
+    pub fn from_env() -> Background {
+        match std::env::var("TETRIS_BACKGROUND") {
+            Ok(path) => Background::Image(path),
+            Err(_) => Background::SolidColor(BACKGROUND),
+        }
+    }
+}
+
+#[derive(Component)]
+pub(crate) struct BackgroundImage;
+
+pub(crate) fn apply_background(
+    background: Res<Background>,
+    asset_server: Res<AssetServer>,
+    windows: Res<Windows>,
+    mut clear_color: ResMut<ClearColor>,
+    images: Query<Entity, With<BackgroundImage>>,
+    mut commands: Commands,
+) {
+    if !background.is_changed() {
+        return;
+    }
+    images.for_each(|entity| commands.entity(entity).despawn_recursive());
+    match &*background {
+        Background::SolidColor(color) => clear_color.0 = *color,
+        Background::Image(path) => {
+            // Shows through if the image can't be loaded
+            clear_color.0 = BACKGROUND;
+            let size = windows
+                .get_primary()
+                .map(|window| Vec2::new(window.width(), window.height()));
+            commands
+                .spawn_bundle(SpriteBundle {
+                    sprite: Sprite {
+                        custom_size: size,
+                        ..Default::default()
+                    },
+                    texture: asset_server.load(path.as_str()),
+                    transform: Transform::from_xyz(0.0, 0.0, Z_BACKGROUND),
+                    ..Default::default()
+                })
+                .insert(BackgroundImage);
+        }
+    }
+}
+
+/// Scales the image to cover the window without stretching it, cropping whichever sides
+/// overhang. Checked every frame, since the image finishes loading after it's spawned.
+pub(crate) fn resize_background_image(
+    windows: Res<Windows>,
+    image_assets: Res<Assets<Image>>,
+    mut images: Query<(&mut Sprite, &Handle<Image>), With<BackgroundImage>>,
+) {
+    let window = match windows.get_primary() {
+        Some(window) => Vec2::new(window.width(), window.height()),
+        None => return,
+    };
+    for (mut sprite, handle) in &mut images {
+        let image_size = match image_assets.get(handle) {
+            Some(image) => image.size(),
+            None => continue,
+        };
+        let size = Some(image_size * (window / image_size).max_element());
+        if sprite.custom_size != size {
+            sprite.custom_size = size;
+        }
+    }
+}
+
+pub(crate) fn drop_missing_background_image(
+    asset_server: Res<AssetServer>,
+    images: Query<(Entity, &Handle<Image>), With<BackgroundImage>>,
+    mut commands: Commands,
+) {
+    for (entity, handle) in &images {
+        if asset_server.get_load_state(handle) == LoadState::Failed {
+            warn!("Couldn't load the background image, using a solid color instead");
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
@@ -0,0 +1,94 @@
+use std::time::Duration;
+
+use bevy::prelude::{
+    AssetServer, Color, Commands, Component, Local, Query, Res, ResMut, State, With,
+};
+use bevy::text::Text;
+use bevy::ui::{UiRect, Val};
+
+use crate::hud::{show_hud_text, HudText};
+use crate::stats::PlayTime;
+use crate::{end_game, FallTimer, GameMode, GameState, NaturalFall, ReduceMotion};
+
+/// How long a game of blitz lasts
+const BLITZ_DURATION: Duration = Duration::from_secs(120);
+/// How often gravity speeds up in blitz
+const SPEED_UP_INTERVAL: Duration = Duration::from_secs(30);
+/// How much each speed-up shortens the fall interval by
+const SPEED_UP: f32 = 0.75;
+/// How little time has to be left for the clock to turn red
+const CLOCK_WARNING: Duration = Duration::from_secs(10);
+
+/// Speeds up gravity as the clock runs and ends the game when it runs out
+pub(crate) fn run_blitz(
+    mode: Res<GameMode>,
+    play_time: Res<PlayTime>,
+    reduce_motion: Res<ReduceMotion>,
+    mut fall_timer: ResMut<FallTimer>,
+    mut natural_fall: ResMut<NaturalFall>,
+    mut game_state: ResMut<State<GameState>>,
+    mut speed_ups: Local<u32>,
+) {
+    if *mode != GameMode::Blitz {
+        return;
+    }
+    let due = (play_time.0.as_secs() / SPEED_UP_INTERVAL.as_secs()) as u32;
+    // Starting a new game resets the fall timers
+    if due < *speed_ups {
+        *speed_ups = 0;
+    }
+    while *speed_ups < due {
+        // Scaled rather than set, so a held soft drop stays three times as fast
+        let duration = fall_timer.0.duration().mul_f32(SPEED_UP);
+        fall_timer.0.set_duration(duration);
+        let duration = natural_fall.timer.duration().mul_f32(SPEED_UP);
+        natural_fall.timer.set_duration(duration);
+        *speed_ups += 1;
+    }
+
+    if play_time.0 >= BLITZ_DURATION {
+        end_game(&mut game_state, &reduce_motion);
+    }
+}
+
+#[derive(Component)]
+pub(crate) struct BlitzClock;
+
+/// Shows the time left in blitz
+pub(crate) fn render_blitz_clock(
+    mode: Res<GameMode>,
+    play_time: Res<PlayTime>,
+    asset_server: Res<AssetServer>,
+    mut texts: Query<&mut Text, With<BlitzClock>>,
+    mut commands: Commands,
+) {
+    if *mode != GameMode::Blitz {
+        return;
+    }
+    let left = BLITZ_DURATION.saturating_sub(play_time.0);
+    // Rounded up, so the clock reads 0:00 only once time is up
+    let seconds = left.as_millis().div_ceil(1000);
+    let value = format!("Time: {}:{:02}", seconds / 60, seconds % 60);
+    let color = if left < CLOCK_WARNING {
+        Color::ORANGE_RED
+    } else {
+        Color::WHITE
+    };
+    let hud = HudText {
+        value,
+        color,
+        font_size: 24.0,
+        position: UiRect {
+            right: Val::Px(10.0),
+            top: Val::Px(10.0),
+            ..Default::default()
+        },
+    };
+    show_hud_text(
+        texts.get_single_mut().ok(),
+        hud,
+        BlitzClock,
+        &asset_server,
+        &mut commands,
+    );
+}
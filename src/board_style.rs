@@ -0,0 +1,131 @@
+use bevy::prelude::{
+    Color, Commands, Component, DespawnRecursiveExt, Entity, Input, KeyCode, Query, Res, ResMut,
+    Transform, Vec3, With,
+};
+use bevy::sprite::{Sprite, SpriteBundle};
+
+use crate::opacity::BackgroundOpacity;
+use crate::{BoardLayout, COLUMNS, ROWS, Z_GRID_PATTERN};
+
+/// The lighter of the checkerboard's two shades, over the black board
+const CHECKER: Color = Color::rgb(0.08, 0.08, 0.08);
+const GRID_LINE: Color = Color::rgb(0.18, 0.18, 0.18);
+/// The thickness of the grid lines, in pixels
+const GRID_LINE_WIDTH: f32 = 1.0;
+
+/// How the board's backdrop is drawn. Cycled with G, or picked with the
+/// `TETRIS_BOARD_STYLE` environment variable (`checker` or `gridlines`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum BackgroundStyle {
+    Flat,
+    /// Alternating shades per cell
+    Checker,
+    /// A line between every row and column
+    GridLines,
+}
+
+impl BackgroundStyle {
+    pub fn from_env() -> BackgroundStyle {
+        match std::env::var("TETRIS_BOARD_STYLE").as_deref() {
+            Ok("checker") => BackgroundStyle::Checker,
+            Ok("gridlines") => BackgroundStyle::GridLines,
+            _ => BackgroundStyle::Flat,
+        }
+    }
+
+    fn next(self) -> BackgroundStyle {
+        match self {
+            BackgroundStyle::Flat => BackgroundStyle::Checker,
+            BackgroundStyle::Checker => BackgroundStyle::GridLines,
+            BackgroundStyle::GridLines => BackgroundStyle::Flat,
+        }
+    }
+}
+
+/// A piece of the pattern drawn over the board's backdrop
+#[derive(Component)]
+pub(crate) struct BoardPattern;
+
+pub(crate) fn cycle_background_style(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut style: ResMut<BackgroundStyle>,
+) {
+    if keyboard_input.just_pressed(KeyCode::G) {
+        *style = style.next();
+    }
+}
+
+fn pattern_sprite(color: Color, translation: Vec3, scale: Vec3) -> SpriteBundle {
+    SpriteBundle {
+        sprite: Sprite {
+            color,
+            ..Default::default()
+        },
+        transform: Transform {
+            translation,
+            scale,
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
+
+/// Redraws the pattern whenever the style, the board's size or the backdrop's opacity changes
+pub(crate) fn update_board_pattern(
+    style: Res<BackgroundStyle>,
+    layout: Res<BoardLayout>,
+    opacity: Res<BackgroundOpacity>,
+    patterns: Query<Entity, With<BoardPattern>>,
+    mut commands: Commands,
+) {
+    if !style.is_changed() && !layout.is_changed() && !opacity.is_changed() {
+        return;
+    }
+    patterns.for_each(|entity| commands.entity(entity).despawn_recursive());
+    let size = layout.cell_size();
+    match *style {
+        BackgroundStyle::Flat => {}
+        BackgroundStyle::Checker => {
+            let mut color = CHECKER;
+            color.set_a(opacity.0);
+            for y in 0..ROWS {
+                for x in (0..COLUMNS).filter(|x| (x + y) % 2 == 1) {
+                    let position = layout.cell_position(x as f32, y as f32);
+                    commands
+                        .spawn_bundle(pattern_sprite(
+                            color,
+                            position.extend(Z_GRID_PATTERN),
+                            Vec3::new(size, size, 1.0),
+                        ))
+                        .insert(BoardPattern);
+                }
+            }
+        }
+        BackgroundStyle::GridLines => {
+            let mut color = GRID_LINE;
+            color.set_a(opacity.0);
+            let middle_x = (COLUMNS - 1) as f32 / 2.0;
+            let middle_y = (ROWS - 1) as f32 / 2.0;
+            for x in 1..COLUMNS {
+                let position = layout.cell_position(x as f32 - 0.5, middle_y);
+                commands
+                    .spawn_bundle(pattern_sprite(
+                        color,
+                        position.extend(Z_GRID_PATTERN),
+                        Vec3::new(GRID_LINE_WIDTH, ROWS as f32 * size, 1.0),
+                    ))
+                    .insert(BoardPattern);
+            }
+            for y in 1..ROWS {
+                let position = layout.cell_position(middle_x, y as f32 - 0.5);
+                commands
+                    .spawn_bundle(pattern_sprite(
+                        color,
+                        position.extend(Z_GRID_PATTERN),
+                        Vec3::new(COLUMNS as f32 * size, GRID_LINE_WIDTH, 1.0),
+                    ))
+                    .insert(BoardPattern);
+            }
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use std::fs;
+
+use bevy::log::warn;
+use bevy::prelude::{
+    Camera2d, Input, KeyCode, OrthographicProjection, Query, Res, ResMut, Transform, Vec2, With,
+};
+
+use crate::storage::data_file;
+
+/// Where the camera framing is kept between runs, in the data directory
+const CAMERA_FILE: &str = "camera.txt";
+/// How far each press of Alt and an arrow key moves the board, in pixels
+const PAN_STEP: f32 = 20.0;
+/// How much each press of Alt and Page Up or Page Down zooms by
+const ZOOM_STEP: f32 = 1.1;
+const MIN_ZOOM: f32 = 0.5;
+const MAX_ZOOM: f32 = 2.0;
+
+/// Frames the board within a stream's layout. Alt and the arrow keys move the board,
+/// Alt and Page Up or Page Down zoom, and Alt+Home resets.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct CameraSettings {
+    pub zoom: f32,
+    /// How far the board is moved from the middle of the window, in pixels
+    pub pan: Vec2,
+}
+
+impl Default for CameraSettings {
+    fn default() -> CameraSettings {
+        CameraSettings {
+            zoom: 1.0,
+            pan: Vec2::ZERO,
+        }
+    }
+}
+
+impl CameraSettings {
+    /// Reads the framing saved by the last run, written as `zoom x y`
+    pub fn load() -> CameraSettings {
+        let path = data_file(CAMERA_FILE);
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(_) => return CameraSettings::default(),
+        };
+        let values: Result<Vec<f32>, _> = text.split_whitespace().map(str::parse).collect();
+        match values.as_deref() {
+            Ok(&[zoom, x, y]) => CameraSettings {
+                zoom: zoom.clamp(MIN_ZOOM, MAX_ZOOM),
+                pan: Vec2::new(x, y),
+            },
+            _ => {
+                warn!("Ignoring malformed camera settings in {}", path.display());
+                CameraSettings::default()
+            }
+        }
+    }
+
+    fn save(&self) {
+        let text = format!("{} {} {}\n", self.zoom, self.pan.x, self.pan.y);
+        let path = data_file(CAMERA_FILE);
+        if let Err(err) = fs::write(&path, text) {
+            warn!(
+                "Couldn't save the camera settings to {}: {}",
+                path.display(),
+                err
+            );
+        }
+    }
+}
+
+pub(crate) fn adjust_camera(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut settings: ResMut<CameraSettings>,
+) {
+    if !keyboard_input.any_pressed([KeyCode::LAlt, KeyCode::RAlt]) {
+        return;
+    }
+    let mut new_settings = *settings;
+    for (key, direction) in [
+        (KeyCode::Left, Vec2::NEG_X),
+        (KeyCode::Right, Vec2::X),
+        (KeyCode::Down, Vec2::NEG_Y),
+        (KeyCode::Up, Vec2::Y),
+    ] {
+        if keyboard_input.just_pressed(key) {
+            new_settings.pan += direction * PAN_STEP;
+        }
+    }
+    if keyboard_input.just_pressed(KeyCode::PageUp) {
+        new_settings.zoom = (new_settings.zoom * ZOOM_STEP).min(MAX_ZOOM);
+    }
+    if keyboard_input.just_pressed(KeyCode::PageDown) {
+        new_settings.zoom = (new_settings.zoom / ZOOM_STEP).max(MIN_ZOOM);
+    }
+    if keyboard_input.just_pressed(KeyCode::Home) {
+        new_settings = CameraSettings::default();
+    }
+    if new_settings != *settings {
+        *settings = new_settings;
+        settings.save();
+    }
+}
+
+pub(crate) fn apply_camera(
+    settings: Res<CameraSettings>,
+    mut cameras: Query<(&mut Transform, &mut OrthographicProjection), With<Camera2d>>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+    for (mut transform, mut projection) in &mut cameras {
+        projection.scale = 1.0 / settings.zoom;
+        // Moving the camera the other way moves the board on screen
+        let position = -settings.pan * projection.scale;
+        transform.translation.x = position.x;
+        transform.translation.y = position.y;
+    }
+}
@@ -0,0 +1,35 @@
+use bevy::prelude::{Added, Query, Res, ResMut, State};
+
+use crate::{
+    end_game, fits_at, FallingSegment, FullGrid, GameState, ReduceMotion, SpawnState, Tile, ROWS,
+};
+
+/// A stricter variant where pieces can't go above the visible board, so the buffer
+/// rows are never used. Enabled by setting the `TETRIS_HARD_CEILING` environment variable.
+/// Every collision check keeps the falling piece under it, and garbage that would lift
+/// the piece through it tops out.
+pub(crate) struct HardCeiling(pub bool);
+
+/// Brings a new piece down from the buffer rows to the top of the board, onto
+/// its spawn flash, ending the game if it doesn't fit there
+pub(crate) fn lower_spawned_piece(
+    hard_ceiling: Res<HardCeiling>,
+    full_grid: Res<FullGrid>,
+    reduce_motion: Res<ReduceMotion>,
+    mut spawned: Query<(&mut Tile, &mut SpawnState), Added<FallingSegment>>,
+    mut game_state: ResMut<State<GameState>>,
+) {
+    if !hard_ceiling.0 || spawned.is_empty() {
+        return;
+    }
+    let highest = spawned.iter().map(|(tile, _)| tile.y).max().unwrap();
+    let drop = highest - (ROWS as i8 - 1);
+    for (mut tile, mut spawn_state) in &mut spawned {
+        tile.y -= drop;
+        spawn_state.tile.y -= drop;
+    }
+
+    if !fits_at(spawned.iter().map(|(tile, _)| *tile), true, &full_grid) {
+        end_game(&mut game_state, &reduce_motion);
+    }
+}
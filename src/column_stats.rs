@@ -0,0 +1,122 @@
+use bevy::prelude::{
+    AssetServer, Color, Commands, Component, DespawnRecursiveExt, Entity, Query, Res, Transform,
+    With,
+};
+use bevy::text::{Text, Text2dBundle, TextAlignment, TextStyle};
+
+use crate::{BoardLayout, FullGrid, COLUMNS, ROWS, Z_OVERLAY};
+
+/// Whether to show each column's height, plus the holes and bumpiness of the stack
+pub(crate) struct ColumnStatsOverlay(pub bool);
+
+#[derive(Component)]
+pub(crate) struct ColumnStatsText;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ColumnStats {
+    /// The number of rows up to and including each column's highest block
+    pub heights: [usize; COLUMNS],
+    /// Empty cells with a block somewhere above them
+    pub holes: usize,
+    /// The sum of the height differences between neighbouring columns
+    pub bumpiness: usize,
+}
+
+impl ColumnStats {
+    pub fn of(full_grid: &FullGrid) -> ColumnStats {
+        let mut heights = [0; COLUMNS];
+        let mut holes = 0;
+        for (x, height) in heights.iter_mut().enumerate() {
+            *height = (0..ROWS)
+                .rev()
+                .find(|&y| full_grid.0[y][x])
+                .map_or(0, |y| y + 1);
+            holes += (0..*height).filter(|&y| !full_grid.0[y][x]).count();
+        }
+        let bumpiness = heights
+            .windows(2)
+            .map(|pair| pair[0].abs_diff(pair[1]))
+            .sum();
+        ColumnStats {
+            heights,
+            holes,
+            bumpiness,
+        }
+    }
+}
+
+pub(crate) fn update_column_stats(
+    overlay: Res<ColumnStatsOverlay>,
+    full_grid: Res<FullGrid>,
+    layout: Res<BoardLayout>,
+    asset_server: Res<AssetServer>,
+    texts: Query<Entity, With<ColumnStatsText>>,
+    mut commands: Commands,
+) {
+    if !overlay.is_changed() && !full_grid.is_changed() && !layout.is_changed() {
+        return;
+    }
+    texts.for_each(|entity| commands.entity(entity).despawn_recursive());
+    if !overlay.0 {
+        return;
+    }
+
+    let stats = ColumnStats::of(&full_grid);
+    let style = TextStyle {
+        font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+        font_size: 16.0,
+        color: Color::WHITE,
+    };
+    for (x, height) in stats.heights.iter().enumerate() {
+        let position = layout.cell_position(x as f32, ROWS as f32);
+        commands
+            .spawn_bundle(Text2dBundle {
+                text: Text::from_section(height.to_string(), style.clone())
+                    .with_alignment(TextAlignment::CENTER),
+                transform: Transform::from_translation(position.extend(Z_OVERLAY)),
+                ..Default::default()
+            })
+            .insert(ColumnStatsText);
+    }
+    commands
+        .spawn_bundle(Text2dBundle {
+            text: Text::from_section(
+                format!("Holes: {}  Bumpiness: {}", stats.holes, stats.bumpiness),
+                style,
+            )
+            .with_alignment(TextAlignment::CENTER),
+            transform: Transform::from_translation(
+                layout
+                    .cell_position((COLUMNS - 1) as f32 / 2.0, -1.0)
+                    .extend(Z_OVERLAY),
+            ),
+            ..Default::default()
+        })
+        .insert(ColumnStatsText);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn measures_heights_holes_and_bumpiness() {
+        let mut full_grid = FullGrid::empty();
+        // A column of three with a hole at the bottom, and a single block beside it
+        full_grid.0[1][0] = true;
+        full_grid.0[2][0] = true;
+        full_grid.0[0][1] = true;
+        let stats = ColumnStats::of(&full_grid);
+        assert_eq!(stats.heights, [3, 1, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(stats.holes, 1);
+        assert_eq!(stats.bumpiness, 2 + 1);
+    }
+
+    #[test]
+    fn an_empty_board_is_flat() {
+        let stats = ColumnStats::of(&FullGrid::empty());
+        assert_eq!(stats.heights, [0; COLUMNS]);
+        assert_eq!(stats.holes, 0);
+        assert_eq!(stats.bumpiness, 0);
+    }
+}
@@ -0,0 +1,109 @@
+use std::time::{Duration, Instant};
+use std::{fs, thread};
+
+use bevy::log::warn;
+use bevy::prelude::{Input, KeyCode, Local, Res, ResMut};
+use bevy::window::{PresentMode, Windows};
+
+use crate::storage::data_file;
+
+/// Frame rates cycled through by the frame cap key, `None` being uncapped
+const FRAME_CAPS: [Option<u32>; 4] = [None, Some(30), Some(60), Some(144)];
+/// The frame rate battery saver holds the game to
+const BATTERY_SAVER_CAP: u32 = 30;
+/// Where battery saver is remembered between runs, in the data directory
+const BATTERY_SAVER_FILE: &str = "battery_saver.txt";
+
+/// Vsync and frame cap, re-applied whenever they change
+pub(crate) struct DisplaySettings {
+    pub vsync: bool,
+    pub frame_cap: Option<u32>,
+    /// Caps the frame rate low to save power, whatever `frame_cap` is. Toggled with
+    /// Ctrl+F3, and on from the start if it was left on or `TETRIS_BATTERY_SAVER` is set.
+    pub battery_saver: bool,
+}
+
+/// Whether battery saver was left on by the last run
+pub(crate) fn load_battery_saver() -> bool {
+    match fs::read_to_string(data_file(BATTERY_SAVER_FILE)) {
+        Ok(text) => text.trim() == "on",
+        Err(_) => false,
+    }
+}
+
+fn save_battery_saver(on: bool) {
+    let text = if on { "on\n" } else { "off\n" };
+    let path = data_file(BATTERY_SAVER_FILE);
+    if let Err(err) = fs::write(&path, text) {
+        warn!(
+            "Couldn't save the battery saver setting to {}: {}",
+            path.display(),
+            err
+        );
+    }
+}
+
+impl DisplaySettings {
+    /// The frame rate to hold to, if any
+    fn effective_frame_cap(&self) -> Option<u32> {
+        if self.battery_saver {
+            Some(
+                self.frame_cap
+                    .map_or(BATTERY_SAVER_CAP, |cap| cap.min(BATTERY_SAVER_CAP)),
+            )
+        } else {
+            self.frame_cap
+        }
+    }
+
+    pub fn present_mode(&self) -> PresentMode {
+        if self.vsync {
+            PresentMode::AutoVsync
+        } else {
+            PresentMode::AutoNoVsync
+        }
+    }
+}
+
+pub(crate) fn change_display_settings(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut settings: ResMut<DisplaySettings>,
+) {
+    if keyboard_input.just_pressed(KeyCode::F2) {
+        settings.vsync = !settings.vsync;
+    }
+    let ctrl = keyboard_input.any_pressed([KeyCode::LControl, KeyCode::RControl]);
+    if ctrl && keyboard_input.just_pressed(KeyCode::F3) {
+        settings.battery_saver = !settings.battery_saver;
+        save_battery_saver(settings.battery_saver);
+    } else if keyboard_input.just_pressed(KeyCode::F3) {
+        let current = FRAME_CAPS
+            .iter()
+            .position(|&cap| cap == settings.frame_cap)
+            .unwrap_or(0);
+        settings.frame_cap = FRAME_CAPS[(current + 1) % FRAME_CAPS.len()];
+    }
+}
+
+pub(crate) fn apply_present_mode(settings: Res<DisplaySettings>, mut windows: ResMut<Windows>) {
+    if !settings.is_changed() {
+        return;
+    }
+    if let Some(window) = windows.get_primary_mut() {
+        window.set_present_mode(settings.present_mode());
+    }
+}
+
+/// Sleeps off whatever is left of the frame's time budget when a frame cap is set
+pub(crate) fn limit_frame_rate(
+    settings: Res<DisplaySettings>,
+    mut last_frame: Local<Option<Instant>>,
+) {
+    if let (Some(cap), Some(last_frame)) = (settings.effective_frame_cap(), *last_frame) {
+        let budget = Duration::from_secs_f64(1.0 / cap as f64);
+        if let Some(remaining) = budget.checked_sub(last_frame.elapsed()) {
+            thread::sleep(remaining);
+        }
+    }
+    *last_frame = Some(Instant::now());
+}
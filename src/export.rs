@@ -0,0 +1,133 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bevy::log::{info, warn};
+use bevy::prelude::{Input, KeyCode, Query, Res, With};
+
+use crate::stats::{LinesCleared, PiecesPlaced, PlayTime};
+use crate::storage::data_file;
+use crate::{FallingSegment, FullGrid, GameMode, Score, Tile, COLUMNS, ROWS};
+
+/// Where Ctrl+B saves the board, in the data directory
+const BOARD_FILE: &str = "board.txt";
+/// Where each finished game's stats are appended, in the data directory
+const STATS_FILE: &str = "stats.csv";
+const STATS_HEADER: &str = "mode,score,lines,time,pieces,date";
+
+/// The visible board as text, top row first: `#` for a block, `@` for the falling piece
+/// and `.` for an empty cell
+fn board_text(full_grid: &FullGrid, falling: &[Tile]) -> String {
+    let mut text = String::with_capacity(ROWS * (COLUMNS + 1));
+    for y in (0..ROWS).rev() {
+        for x in 0..COLUMNS {
+            let tile = Tile {
+                x: x as i8,
+                y: y as i8,
+            };
+            text.push(if falling.contains(&tile) {
+                '@'
+            } else if full_grid.0[y][x] {
+                '#'
+            } else {
+                '.'
+            });
+        }
+        text.push('\n');
+    }
+    text
+}
+
+/// Logs the board and saves it to a file, for sharing puzzles and bug reports
+pub(crate) fn export_board(
+    keyboard_input: Res<Input<KeyCode>>,
+    full_grid: Option<Res<FullGrid>>,
+    falling: Query<&Tile, With<FallingSegment>>,
+) {
+    let ctrl = keyboard_input.any_pressed([KeyCode::LControl, KeyCode::RControl]);
+    if !ctrl || !keyboard_input.just_pressed(KeyCode::B) {
+        return;
+    }
+    let full_grid = match full_grid {
+        Some(full_grid) => full_grid,
+        None => return,
+    };
+    let falling: Vec<Tile> = falling.iter().copied().collect();
+    let text = board_text(&full_grid, &falling);
+    info!("Board:\n{}", text);
+    let path = data_file(BOARD_FILE);
+    match fs::write(&path, &text) {
+        Ok(()) => info!("Saved the board to {}", path.display()),
+        Err(err) => warn!("Couldn't save the board to {}: {}", path.display(), err),
+    }
+}
+
+/// The current UTC time, like `2022-08-01 17:30:00`
+fn utc_timestamp() -> String {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs());
+    let (days, time) = (seconds / 86400, seconds % 86400);
+    // Converts days since 1970 to a civil date, counting years from March
+    // so that the leap day comes last
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = era * 400 + year_of_era + u64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+fn append_stats_row(path: &Path, row: &str) -> io::Result<()> {
+    let is_new = !path.exists();
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    if is_new {
+        writeln!(file, "{}", STATS_HEADER)?;
+    }
+    writeln!(file, "{}", row)
+}
+
+/// Appends the finished game to a CSV file, for tracking progress across sessions
+pub(crate) fn export_stats_csv(
+    mode: Res<GameMode>,
+    score: Res<Score>,
+    lines: Res<LinesCleared>,
+    play_time: Res<PlayTime>,
+    pieces: Res<PiecesPlaced>,
+) {
+    let row = format!(
+        "{},{},{},{:.1},{},{}",
+        mode.name(),
+        score.0,
+        lines.0,
+        play_time.0.as_secs_f32(),
+        pieces.0,
+        utc_timestamp()
+    );
+    let path = data_file(STATS_FILE);
+    if let Err(err) = append_stats_row(&path, &row) {
+        warn!(
+            "Couldn't save the game's stats to {}: {}",
+            path.display(),
+            err
+        );
+    }
+}
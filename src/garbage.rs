@@ -0,0 +1,383 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use bevy::prelude::{
+    AssetServer, Color, Commands, Component, Local, Mut, Query, Res, ResMut, State, Transform,
+    Vec3, With, Without,
+};
+use bevy::sprite::{Sprite, SpriteBundle};
+use bevy::text::Text;
+use bevy::time::{Time, Timer};
+use bevy::ui::{UiRect, Val};
+use rand::rngs::SmallRng;
+use rand::Rng;
+
+use crate::ceiling::HardCeiling;
+use crate::hud::{show_hud_text, HudText};
+use crate::stats::LinesCleared;
+use crate::{
+    end_game, fits_at, tile_sprite, BoardLayout, FallingSegment, FullGrid, GameMode, GameState,
+    ReduceMotion, Tile, COLUMNS, ROWS, Z_OVERLAY,
+};
+
+const GARBAGE_COLOR: Color = Color::DARK_GRAY;
+/// The most garbage rows cheese mode can start with, leaving room to play above them
+pub(crate) const MAX_CHEESE_ROWS: u32 = 12;
+const CHEESE_REFILL_DELAY: Duration = Duration::from_secs(1);
+/// How often garbage is sent in versus practice
+const ATTACK_INTERVAL: Duration = Duration::from_secs(8);
+/// How long sent garbage waits in the queue before it rises
+const GARBAGE_WARNING: Duration = Duration::from_secs(3);
+const GARBAGE_METER: Color = Color::RED;
+
+/// How many rows at the bottom of the board are garbage
+pub(crate) struct GarbageRows(pub usize);
+
+/// Push the stack up by `count` rows and fill the gap with garbage,
+/// each row having a single random hole.
+pub(crate) fn add_garbage_lines<'a>(
+    count: usize,
+    full_grid: &mut FullGrid,
+    garbage_rows: &mut GarbageRows,
+    locked_tiles: impl Iterator<Item = Mut<'a, Tile>>,
+    commands: &mut Commands,
+    rng: &mut SmallRng,
+) {
+    let count = count.min(ROWS);
+    for mut tile in locked_tiles {
+        tile.y += count as i8;
+    }
+    full_grid.0.rotate_right(count);
+    for y in 0..count {
+        let hole = rng.gen_range(0..COLUMNS);
+        full_grid.0[y] = [true; COLUMNS];
+        full_grid.0[y][hole] = false;
+        for x in (0..COLUMNS).filter(|&x| x != hole) {
+            let (x, y) = (x as i8, y as i8);
+            commands
+                .spawn()
+                .insert_bundle(tile_sprite(GARBAGE_COLOR))
+                .insert(Tile { x, y });
+        }
+    }
+    garbage_rows.0 += count;
+}
+
+pub(crate) fn refill_cheese(
+    time: Res<Time>,
+    mode: Res<GameMode>,
+    mut garbage_rows: ResMut<GarbageRows>,
+    mut full_grid: ResMut<FullGrid>,
+    mut rng: ResMut<SmallRng>,
+    mut locked: Query<&mut Tile, Without<FallingSegment>>,
+    mut falling: Query<&mut Tile, With<FallingSegment>>,
+    hard_ceiling: Res<HardCeiling>,
+    reduce_motion: Res<ReduceMotion>,
+    mut game_state: ResMut<State<GameState>>,
+    mut commands: Commands,
+    mut refill_timer: Local<Option<Timer>>,
+) {
+    let rows = match *mode {
+        GameMode::Cheese { rows } => rows,
+        _ => return,
+    };
+    if garbage_rows.0 != 0 {
+        *refill_timer = None;
+        return;
+    }
+    // Give the goal counter a moment to celebrate before refilling
+    let timer = refill_timer.get_or_insert_with(|| Timer::new(CHEESE_REFILL_DELAY, false));
+    if !timer.tick(time.delta()).finished() {
+        return;
+    }
+    *refill_timer = None;
+    add_garbage_lines(
+        rows as usize,
+        &mut full_grid,
+        &mut garbage_rows,
+        locked.iter_mut(),
+        &mut commands,
+        &mut rng,
+    );
+    lift_falling_piece(
+        &mut falling,
+        &full_grid,
+        &hard_ceiling,
+        &reduce_motion,
+        &mut game_state,
+    );
+}
+
+/// Lifts the falling piece clear of newly added garbage, ending the game
+/// if that takes it through the hard ceiling
+fn lift_falling_piece(
+    falling: &mut Query<&mut Tile, With<FallingSegment>>,
+    full_grid: &FullGrid,
+    hard_ceiling: &HardCeiling,
+    reduce_motion: &ReduceMotion,
+    game_state: &mut State<GameState>,
+) {
+    while !fits_at(falling.iter().copied(), false, full_grid) {
+        for mut tile in falling.iter_mut() {
+            tile.y += 1;
+        }
+    }
+    if !fits_at(falling.iter().copied(), hard_ceiling.0, full_grid) {
+        end_game(game_state, reduce_motion);
+    }
+}
+
+/// Garbage sent in versus practice that hasn't risen yet, oldest first
+pub(crate) struct GarbageQueue {
+    attack_timer: Timer,
+    pending: VecDeque<(usize, Timer)>,
+}
+
+impl GarbageQueue {
+    pub fn new() -> GarbageQueue {
+        GarbageQueue {
+            attack_timer: Timer::new(ATTACK_INTERVAL, true),
+            pending: VecDeque::new(),
+        }
+    }
+
+    fn pending_lines(&self) -> usize {
+        self.pending.iter().map(|(lines, _)| lines).sum()
+    }
+
+    /// Cancels pending garbage, oldest first
+    fn cancel(&mut self, mut lines: usize) {
+        while let Some((pending, _)) = self.pending.front_mut() {
+            if lines < *pending {
+                *pending -= lines;
+                return;
+            }
+            lines -= *pending;
+            self.pending.pop_front();
+        }
+    }
+}
+
+/// Sends garbage at the player every so often. Clearing lines cancels pending garbage
+/// before it rises.
+pub(crate) fn versus_garbage(
+    time: Res<Time>,
+    mode: Res<GameMode>,
+    lines: Res<LinesCleared>,
+    mut queue: ResMut<GarbageQueue>,
+    mut garbage_rows: ResMut<GarbageRows>,
+    mut full_grid: ResMut<FullGrid>,
+    mut rng: ResMut<SmallRng>,
+    mut locked: Query<&mut Tile, Without<FallingSegment>>,
+    mut falling: Query<&mut Tile, With<FallingSegment>>,
+    hard_ceiling: Res<HardCeiling>,
+    reduce_motion: Res<ReduceMotion>,
+    mut game_state: ResMut<State<GameState>>,
+    mut commands: Commands,
+    mut last_lines: Local<u32>,
+) {
+    if *mode != GameMode::Versus {
+        return;
+    }
+    // Lines goes back to zero when a new game starts
+    let cleared = lines.0.saturating_sub(*last_lines);
+    *last_lines = lines.0;
+    if cleared != 0 {
+        queue.cancel(cleared as usize);
+    }
+
+    if queue.attack_timer.tick(time.delta()).just_finished() {
+        let lines = rng.gen_range(1..=4);
+        queue
+            .pending
+            .push_back((lines, Timer::new(GARBAGE_WARNING, false)));
+    }
+
+    let mut rising = 0;
+    for (lines, timer) in &mut queue.pending {
+        if timer.tick(time.delta()).finished() {
+            rising += *lines;
+        }
+    }
+    if rising == 0 {
+        return;
+    }
+    queue.pending.retain(|(_, timer)| !timer.finished());
+    add_garbage_lines(
+        rising,
+        &mut full_grid,
+        &mut garbage_rows,
+        locked.iter_mut(),
+        &mut commands,
+        &mut rng,
+    );
+    lift_falling_piece(
+        &mut falling,
+        &full_grid,
+        &hard_ceiling,
+        &reduce_motion,
+        &mut game_state,
+    );
+}
+
+/// The bar beside the board showing how much garbage is about to rise
+#[derive(Component)]
+pub(crate) struct GarbageMeter;
+
+fn garbage_meter_transform(layout: &BoardLayout, lines: usize) -> Transform {
+    let lines = lines.min(ROWS) as f32;
+    // A thin bar just left of the board, growing up from its bottom edge
+    let position = layout.cell_position(-0.75, (lines - 1.0) / 2.0);
+    Transform {
+        translation: position.extend(Z_OVERLAY),
+        scale: Vec3::new(layout.cell_size() / 3.0, lines * layout.cell_size(), 1.0),
+        ..Default::default()
+    }
+}
+
+pub(crate) fn render_garbage_meter(
+    mode: Res<GameMode>,
+    queue: Res<GarbageQueue>,
+    layout: Res<BoardLayout>,
+    mut meters: Query<&mut Transform, With<GarbageMeter>>,
+    mut commands: Commands,
+) {
+    if *mode != GameMode::Versus {
+        return;
+    }
+    let transform = garbage_meter_transform(&layout, queue.pending_lines());
+    match meters.get_single_mut() {
+        Ok(mut meter) => {
+            if queue.is_changed() || layout.is_changed() {
+                *meter = transform;
+            }
+        }
+        Err(_) => {
+            commands
+                .spawn_bundle(SpriteBundle {
+                    sprite: Sprite {
+                        color: GARBAGE_METER,
+                        ..Default::default()
+                    },
+                    transform,
+                    ..Default::default()
+                })
+                .insert(GarbageMeter);
+        }
+    }
+}
+
+#[derive(Component)]
+pub(crate) struct GoalText;
+
+/// Shows how much garbage is left to clear in cheese mode
+pub(crate) fn render_goal_counter(
+    mode: Res<GameMode>,
+    garbage_rows: Res<GarbageRows>,
+    asset_server: Res<AssetServer>,
+    mut texts: Query<&mut Text, With<GoalText>>,
+    mut commands: Commands,
+) {
+    if !matches!(*mode, GameMode::Cheese { .. }) {
+        return;
+    }
+    let (value, color) = match garbage_rows.0 {
+        0 => ("Cleared!".to_string(), Color::GREEN),
+        remaining => (format!("Garbage left: {}", remaining), Color::WHITE),
+    };
+    let hud = HudText {
+        value,
+        color,
+        font_size: 24.0,
+        position: UiRect {
+            right: Val::Px(10.0),
+            top: Val::Px(10.0),
+            ..Default::default()
+        },
+    };
+    show_hud_text(
+        texts.get_single_mut().ok(),
+        hud,
+        GoalText,
+        &asset_server,
+        &mut commands,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{advance, drive_state, fill, game, ground_piece, run, spawn_piece};
+    use crate::tetrominoes::PieceKind;
+
+    fn queue(lines: &[usize]) -> GarbageQueue {
+        let mut queue = GarbageQueue::new();
+        for &lines in lines {
+            queue
+                .pending
+                .push_back((lines, Timer::new(GARBAGE_WARNING, false)));
+        }
+        queue
+    }
+
+    fn pending(queue: &GarbageQueue) -> Vec<usize> {
+        queue.pending.iter().map(|&(lines, _)| lines).collect()
+    }
+
+    #[test]
+    fn cancelling_takes_from_the_oldest_garbage() {
+        let mut garbage = queue(&[3, 2]);
+        garbage.cancel(1);
+        assert_eq!(pending(&garbage), [2, 2]);
+        assert_eq!(garbage.pending_lines(), 4);
+    }
+
+    #[test]
+    fn cancelling_a_whole_attack_removes_it() {
+        let mut garbage = queue(&[3, 2]);
+        garbage.cancel(3);
+        assert_eq!(pending(&garbage), [2]);
+    }
+
+    #[test]
+    fn cancelling_carries_over_to_the_next_attack() {
+        let mut garbage = queue(&[1, 4, 2]);
+        garbage.cancel(3);
+        assert_eq!(pending(&garbage), [2, 2]);
+    }
+
+    #[test]
+    fn cancelling_more_than_is_pending_empties_the_queue() {
+        let mut garbage = queue(&[1, 2]);
+        garbage.cancel(4);
+        assert!(garbage.pending.is_empty());
+        assert_eq!(garbage.pending_lines(), 0);
+    }
+
+    /// Raises four rows of garbage under a piece resting on a stack that nearly
+    /// reaches the top, and says whether the game was ended
+    fn garbage_tops_out(hard_ceiling: bool) -> bool {
+        let mut world = game();
+        world.insert_resource(GameMode::Versus);
+        world.insert_resource(queue(&[4]));
+        world.insert_resource(ReduceMotion(true));
+        world.insert_resource(State::new(GameState::Playing));
+        drive_state(&mut world);
+        let stack: Vec<_> = (0..15).flat_map(|y| (4..8).map(move |x| (x, y))).collect();
+        fill(&mut world, &stack);
+        spawn_piece(&mut world, PieceKind::T);
+        ground_piece(&mut world);
+        world.insert_resource(HardCeiling(hard_ceiling));
+
+        advance(&mut world, GARBAGE_WARNING);
+        run(&mut world, versus_garbage);
+        drive_state(&mut world);
+        *world.resource::<State<GameState>>().current() == GameState::GameOver
+    }
+
+    #[test]
+    fn lifting_the_piece_through_the_hard_ceiling_tops_out() {
+        assert!(garbage_tops_out(true));
+        assert!(!garbage_tops_out(false));
+    }
+}
@@ -0,0 +1,165 @@
+use crate::{FullGrid, Tile, BUFFER_ROWS, COLUMNS, ROWS};
+
+/// How the stack falls after a line clear
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum GravityMode {
+    /// Everything above a cleared row moves down by one row
+    Naive,
+    /// Each connected group of blocks falls until it lands, which can fill
+    /// more rows and set off chain clears
+    Sticky,
+}
+
+impl GravityMode {
+    /// Sticky gravity if `TETRIS_GRAVITY` is `sticky`
+    pub fn from_env() -> GravityMode {
+        match std::env::var("TETRIS_GRAVITY").as_deref() {
+            Ok("sticky") => GravityMode::Sticky,
+            _ => GravityMode::Naive,
+        }
+    }
+}
+
+/// The groups of orthogonally connected blocks, lowest group first
+fn connected_groups(full_grid: &FullGrid) -> Vec<Vec<(usize, usize)>> {
+    let mut seen = [[false; COLUMNS]; ROWS + BUFFER_ROWS];
+    let mut groups = Vec::new();
+    for y in 0..ROWS + BUFFER_ROWS {
+        for x in 0..COLUMNS {
+            if !full_grid.0[y][x] || seen[y][x] {
+                continue;
+            }
+            seen[y][x] = true;
+            let mut group = vec![(x, y)];
+            let mut frontier = vec![(x, y)];
+            while let Some((x, y)) = frontier.pop() {
+                let neighbors = [
+                    x.checked_sub(1).map(|x| (x, y)),
+                    (x + 1 < COLUMNS).then_some((x + 1, y)),
+                    y.checked_sub(1).map(|y| (x, y)),
+                    (y + 1 < ROWS + BUFFER_ROWS).then_some((x, y + 1)),
+                ];
+                for (x, y) in neighbors.into_iter().flatten() {
+                    if full_grid.0[y][x] && !seen[y][x] {
+                        seen[y][x] = true;
+                        group.push((x, y));
+                        frontier.push((x, y));
+                    }
+                }
+            }
+            groups.push(group);
+        }
+    }
+    groups
+}
+
+/// Drops each connected group of blocks as far as it'll go, lowest first,
+/// until none can fall any further. Returns where each block that moved
+/// started and how many rows it fell.
+pub(crate) fn settle(full_grid: &mut FullGrid) -> Vec<(Tile, i8)> {
+    // Where the block now in each cell started
+    let mut origins = [[None; COLUMNS]; ROWS + BUFFER_ROWS];
+    for (y, row) in origins.iter_mut().enumerate() {
+        for (x, origin) in row.iter_mut().enumerate() {
+            *origin = Some(Tile {
+                x: x as i8,
+                y: y as i8,
+            });
+        }
+    }
+    loop {
+        let mut moved = false;
+        for group in connected_groups(full_grid) {
+            for &(x, y) in &group {
+                full_grid.0[y][x] = false;
+            }
+            let mut distance = 0;
+            while group
+                .iter()
+                .all(|&(x, y)| y > distance && !full_grid.0[y - distance - 1][x])
+            {
+                distance += 1;
+            }
+            let moving: Vec<_> = group.iter().map(|&(x, y)| origins[y][x]).collect();
+            for &(x, y) in &group {
+                origins[y][x] = None;
+            }
+            for (&(x, y), origin) in group.iter().zip(moving) {
+                full_grid.0[y - distance][x] = true;
+                origins[y - distance][x] = origin;
+            }
+            moved |= distance > 0;
+        }
+        if !moved {
+            break;
+        }
+    }
+
+    let mut drops = Vec::new();
+    for (y, row) in origins.iter().enumerate() {
+        for (x, origin) in row.iter().enumerate() {
+            if let Some(origin) = origin {
+                if full_grid.0[y][x] && origin.y != y as i8 {
+                    drops.push((*origin, origin.y - y as i8));
+                }
+            }
+        }
+    }
+    drops
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid(cells: &[(usize, usize)]) -> FullGrid {
+        let mut full_grid = FullGrid::empty();
+        for &(x, y) in cells {
+            full_grid.0[y][x] = true;
+        }
+        full_grid
+    }
+
+    #[test]
+    fn groups_fall_as_a_unit() {
+        // An L hanging over a gap, and a block resting on the floor
+        let mut full_grid = grid(&[(0, 0), (6, 2), (7, 2), (7, 3)]);
+        let drops = settle(&mut full_grid);
+        assert_eq!(
+            full_grid.0[..4],
+            grid(&[(0, 0), (6, 0), (7, 0), (7, 1)]).0[..4]
+        );
+        assert_eq!(drops.len(), 3);
+        assert!(drops.iter().all(|&(_, distance)| distance == 2));
+        assert!(drops.contains(&(Tile { x: 7, y: 3 }, 2)));
+    }
+
+    #[test]
+    fn groups_resting_on_the_stack_stay_put() {
+        let mut full_grid = grid(&[(3, 0), (3, 1), (4, 1), (4, 2)]);
+        assert!(settle(&mut full_grid).is_empty());
+        assert_eq!(
+            full_grid.0[..3],
+            grid(&[(3, 0), (3, 1), (4, 1), (4, 2)]).0[..3]
+        );
+    }
+
+    #[test]
+    fn cascades_until_nothing_can_fall() {
+        // The lower group lands first, then the one above lands on it
+        let mut full_grid = grid(&[(2, 2), (2, 5), (3, 5)]);
+        let drops = settle(&mut full_grid);
+        assert_eq!(full_grid.0[..6], grid(&[(2, 0), (2, 1), (3, 1)]).0[..6]);
+        assert!(drops.contains(&(Tile { x: 2, y: 2 }, 2)));
+        assert!(drops.contains(&(Tile { x: 3, y: 5 }, 4)));
+    }
+
+    #[test]
+    fn settling_can_fill_a_row() {
+        let mut row: Vec<_> = (1..COLUMNS).map(|x| (x, 0)).collect();
+        row.push((0, 1));
+        let mut full_grid = grid(&row);
+        assert_eq!(settle(&mut full_grid), [(Tile { x: 0, y: 1 }, 1)]);
+        assert_eq!(full_grid.0[0], [true; COLUMNS]);
+    }
+}
@@ -0,0 +1,74 @@
+use bevy::prelude::{
+    Color, Commands, Component, DespawnRecursiveExt, Entity, Local, Query, Res, Transform, Vec3,
+    With,
+};
+use bevy::sprite::{Sprite, SpriteBundle};
+use bevy::time::Time;
+
+use crate::ceiling::HardCeiling;
+use crate::{can_fall, BoardLayout, FallingSegment, FullGrid, ReduceMotion, Tile, Z_FALLING};
+
+const GROUNDED_MARKER: Color = Color::WHITE;
+/// The thickness of the marks, in pixels
+const GROUNDED_MARKER_HEIGHT: f32 = 3.0;
+
+/// Underlines the falling piece while it's resting on the stack, about to lock
+#[derive(Component)]
+pub(crate) struct GroundedMarker;
+
+pub(crate) fn update_grounded_markers(
+    time: Res<Time>,
+    layout: Res<BoardLayout>,
+    full_grid: Res<FullGrid>,
+    hard_ceiling: Res<HardCeiling>,
+    reduce_motion: Res<ReduceMotion>,
+    segments: Query<&Tile, With<FallingSegment>>,
+    mut markers: Query<(Entity, &mut Sprite), With<GroundedMarker>>,
+    mut drawn: Local<Vec<Tile>>,
+    mut commands: Commands,
+) {
+    let tiles: Vec<Tile> = segments.iter().copied().collect();
+    let mut resting_on = Vec::new();
+    if !tiles.is_empty() && !can_fall(tiles.iter().copied(), hard_ceiling.0, &full_grid) {
+        // The bottom cell of each column the piece covers
+        resting_on.extend(tiles.iter().filter(|tile| {
+            !tiles.contains(&Tile {
+                y: tile.y - 1,
+                ..**tile
+            })
+        }));
+    }
+
+    let alpha = if reduce_motion.0 {
+        0.7
+    } else {
+        0.55 + 0.3 * (time.seconds_since_startup() as f32 * 10.0).sin()
+    };
+    if *drawn != resting_on || layout.is_changed() {
+        markers.for_each(|(entity, _)| commands.entity(entity).despawn_recursive());
+        let mut color = GROUNDED_MARKER;
+        color.set_a(alpha);
+        for tile in &resting_on {
+            let position = layout.cell_position(tile.x as f32, tile.y as f32 - 0.5);
+            commands
+                .spawn_bundle(SpriteBundle {
+                    sprite: Sprite {
+                        color,
+                        ..Default::default()
+                    },
+                    transform: Transform {
+                        translation: position.extend(Z_FALLING),
+                        scale: Vec3::new(layout.cell_size(), GROUNDED_MARKER_HEIGHT, 1.0),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .insert(GroundedMarker);
+        }
+        *drawn = resting_on;
+    }
+
+    for (_, mut sprite) in &mut markers {
+        sprite.color.set_a(alpha);
+    }
+}
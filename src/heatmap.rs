@@ -0,0 +1,43 @@
+use bevy::prelude::{Color, Input, KeyCode, Query, Res, ResMut, Without};
+use bevy::sprite::Sprite;
+
+use crate::{BaseColor, FallingSegment, LockFlash, Tile, ROWS};
+
+/// A debug view that colors the stack by height, from green at the bottom to red at the top.
+/// Toggled with H.
+pub(crate) struct HeatmapView(pub bool);
+
+pub(crate) fn toggle_heatmap(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut heatmap: ResMut<HeatmapView>,
+) {
+    if keyboard_input.just_pressed(KeyCode::H) {
+        heatmap.0 = !heatmap.0;
+    }
+}
+
+fn height_color(y: i8) -> Color {
+    let t = (f32::from(y) / (ROWS - 1) as f32).clamp(0.0, 1.0);
+    Color::rgb(t, 1.0 - t, 0.0)
+}
+
+pub(crate) fn apply_heatmap(
+    heatmap: Res<HeatmapView>,
+    mut tiles: Query<
+        (&Tile, &mut Sprite, &BaseColor),
+        (Without<FallingSegment>, Without<LockFlash>),
+    >,
+) {
+    if !heatmap.0 && !heatmap.is_changed() {
+        return;
+    }
+    for (tile, mut sprite, base) in &mut tiles {
+        let alpha = sprite.color.a();
+        sprite.color = if heatmap.0 {
+            height_color(tile.y)
+        } else {
+            base.0
+        };
+        sprite.color.set_a(alpha);
+    }
+}
@@ -0,0 +1,53 @@
+use bevy::prelude::{AssetServer, Color, Commands, Component, Mut, TextBundle};
+use bevy::text::{Text, TextStyle};
+use bevy::ui::{PositionType, Style, UiRect, Val};
+
+/// A line of text pinned to the edge of the window
+pub(crate) struct HudText {
+    pub value: String,
+    pub color: Color,
+    pub font_size: f32,
+    /// Offsets from the window's edges
+    pub position: UiRect<Val>,
+}
+
+/// Shows `hud` in the text that's already on screen, or spawns it with `marker`
+/// if there isn't one yet. The text is only touched when it differs, so the UI
+/// isn't laid out again every frame.
+pub(crate) fn show_hud_text(
+    existing: Option<Mut<Text>>,
+    hud: HudText,
+    marker: impl Component,
+    asset_server: &AssetServer,
+    commands: &mut Commands,
+) {
+    match existing {
+        Some(mut text) => {
+            let section = &text.sections[0];
+            if section.value != hud.value || section.style.color != hud.color {
+                let section = &mut text.sections[0];
+                section.value = hud.value;
+                section.style.color = hud.color;
+            }
+        }
+        None => {
+            commands
+                .spawn_bundle(
+                    TextBundle::from_section(
+                        hud.value,
+                        TextStyle {
+                            font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                            font_size: hud.font_size,
+                            color: hud.color,
+                        },
+                    )
+                    .with_style(Style {
+                        position_type: PositionType::Absolute,
+                        position: hud.position,
+                        ..Default::default()
+                    }),
+                )
+                .insert(marker);
+        }
+    }
+}
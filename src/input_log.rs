@@ -0,0 +1,79 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use bevy::input::keyboard::KeyboardInput;
+use bevy::input::ButtonState;
+use bevy::log::info;
+use bevy::prelude::{EventReader, Input, KeyCode, Res, ResMut};
+use bevy::time::Time;
+
+/// How far back the log reaches
+const LOG_SPAN: Duration = Duration::from_secs(5);
+
+struct LoggedKey {
+    frame: u64,
+    /// Since startup, as of the start of the frame that read the key
+    time: Duration,
+    key: KeyCode,
+    state: ButtonState,
+}
+
+/// Every key press and release from the last few seconds, for diagnosing dropped inputs.
+/// Printed with Ctrl+I.
+#[derive(Default)]
+pub(crate) struct InputLog {
+    frame: u64,
+    keys: VecDeque<LoggedKey>,
+}
+
+pub(crate) fn record_inputs(
+    time: Res<Time>,
+    mut events: EventReader<KeyboardInput>,
+    mut log: ResMut<InputLog>,
+) {
+    log.frame += 1;
+    let now = time.time_since_startup();
+    for event in events.iter() {
+        if let Some(key) = event.key_code {
+            let frame = log.frame;
+            log.keys.push_back(LoggedKey {
+                frame,
+                time: now,
+                key,
+                state: event.state,
+            });
+        }
+    }
+    while log
+        .keys
+        .front()
+        .is_some_and(|logged| now - logged.time > LOG_SPAN)
+    {
+        log.keys.pop_front();
+    }
+}
+
+pub(crate) fn export_input_log(keyboard_input: Res<Input<KeyCode>>, log: Res<InputLog>) {
+    let ctrl = keyboard_input.any_pressed([KeyCode::LControl, KeyCode::RControl]);
+    if !ctrl || !keyboard_input.just_pressed(KeyCode::I) {
+        return;
+    }
+    info!(
+        "Input log, last {}s (frame {}):",
+        LOG_SPAN.as_secs(),
+        log.frame
+    );
+    for logged in &log.keys {
+        let state = match logged.state {
+            ButtonState::Pressed => "pressed",
+            ButtonState::Released => "released",
+        };
+        info!(
+            "  frame {:>6} @ {:>9.3}s  {:?} {}",
+            logged.frame,
+            logged.time.as_secs_f64(),
+            logged.key,
+            state
+        );
+    }
+}
@@ -0,0 +1,86 @@
+use std::time::Duration;
+
+use bevy::prelude::{
+    Commands, Component, Entity, Input, KeyCode, Query, RemovedComponents, Res, ResMut, State,
+    With, Without,
+};
+use bevy::sprite::Sprite;
+use bevy::time::{Time, Timer};
+
+use crate::opacity::TileOpacity;
+use crate::{FallingSegment, GameState, Tile};
+
+/// How long a locked tile stays visible, the second half of which it spends fading out
+const VANISH_DURATION: Duration = Duration::from_millis(1000);
+/// How long the peek key shows the board for
+const PEEK_DURATION: Duration = Duration::from_millis(750);
+
+/// A challenge where locked pieces disappear, though they still fill the board.
+/// Enabled by setting the `TETRIS_INVISIBLE` environment variable.
+pub(crate) struct InvisibleMode(pub bool);
+
+/// Counts down a briefly revealed board, if the player is peeking
+#[derive(Default)]
+pub(crate) struct Peek(Option<Timer>);
+
+/// Fades out a just-locked tile
+#[derive(Component)]
+pub(crate) struct Vanish(Timer);
+
+/// A locked tile that has finished fading out
+#[derive(Component)]
+pub(crate) struct Hidden;
+
+pub(crate) fn start_vanishing(
+    invisible_mode: Res<InvisibleMode>,
+    locked: RemovedComponents<FallingSegment>,
+    tiles: Query<&Tile>,
+    mut commands: Commands,
+) {
+    if !invisible_mode.0 {
+        return;
+    }
+    for entity in locked.iter() {
+        // Skips tiles that were cleared in the same frame they locked
+        if tiles.get(entity).is_ok() {
+            commands
+                .entity(entity)
+                .insert(Vanish(Timer::new(VANISH_DURATION, false)));
+        }
+    }
+}
+
+pub(crate) fn peek(keyboard_input: Res<Input<KeyCode>>, time: Res<Time>, mut peek: ResMut<Peek>) {
+    if keyboard_input.just_pressed(KeyCode::P) {
+        peek.0 = Some(Timer::new(PEEK_DURATION, false));
+    } else if let Some(timer) = &mut peek.0 {
+        if timer.tick(time.delta()).finished() {
+            peek.0 = None;
+        }
+    }
+}
+
+pub(crate) fn animate_vanish(
+    time: Res<Time>,
+    peek: Res<Peek>,
+    opacity: Res<TileOpacity>,
+    game_state: Res<State<GameState>>,
+    mut vanishing: Query<(Entity, &mut Sprite, &mut Vanish)>,
+    mut hidden: Query<&mut Sprite, (With<Hidden>, Without<Vanish>)>,
+    mut commands: Commands,
+) {
+    let revealed = peek.0.is_some() || *game_state.current() != GameState::Playing;
+    for (entity, mut sprite, mut vanish) in &mut vanishing {
+        if vanish.0.tick(time.delta()).finished() {
+            commands.entity(entity).remove::<Vanish>().insert(Hidden);
+        } else if revealed {
+            sprite.color.set_a(opacity.0);
+        } else {
+            let fade = (2.0 * (1.0 - vanish.0.percent())).min(1.0);
+            sprite.color.set_a(fade * opacity.0);
+        }
+    }
+    for mut sprite in &mut hidden {
+        sprite.color.set_a(if revealed { opacity.0 } else { 0.0 });
+    }
+}
@@ -0,0 +1,81 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use bevy::prelude::{
+    AssetServer, Color, Commands, Component, DespawnRecursiveExt, Entity, Query, Res, ResMut, With,
+};
+use bevy::text::Text;
+use bevy::ui::{UiRect, Val};
+
+use crate::hud::{show_hud_text, HudText};
+
+/// How many measurements the displayed average covers
+const SAMPLES: usize = 20;
+
+/// Whether to measure and show how long moves take to reach the screen
+pub(crate) struct LatencyTest(pub bool);
+
+/// Follows the latest keyboard move from its key press to the screen
+#[derive(Default)]
+pub(crate) struct LatencyProbe {
+    /// The start of the frame that applied the move, which is when its key press was read
+    pub pressed: Option<Instant>,
+    /// Set once the move has reached the sprites' transforms,
+    /// so it's on screen by the time the next frame starts
+    pub drawn: Option<Instant>,
+    samples: VecDeque<Duration>,
+}
+
+impl LatencyProbe {
+    fn average(&self) -> Option<Duration> {
+        let total: Duration = self.samples.iter().sum();
+        Some(total / u32::try_from(self.samples.len()).ok().filter(|&n| n != 0)?)
+    }
+}
+
+#[derive(Component)]
+pub(crate) struct LatencyText;
+
+/// Runs at the start of a frame, after the previous frame has been rendered
+pub(crate) fn finish_latency_sample(mut probe: ResMut<LatencyProbe>) {
+    if let Some(pressed) = probe.drawn {
+        probe.drawn = None;
+        if probe.samples.len() == SAMPLES {
+            probe.samples.pop_front();
+        }
+        probe.samples.push_back(pressed.elapsed());
+    }
+}
+
+pub(crate) fn show_latency(
+    latency_test: Res<LatencyTest>,
+    probe: Res<LatencyProbe>,
+    asset_server: Res<AssetServer>,
+    mut texts: Query<(Entity, &mut Text), With<LatencyText>>,
+    mut commands: Commands,
+) {
+    if !latency_test.0 {
+        texts.for_each(|(entity, _)| commands.entity(entity).despawn_recursive());
+        return;
+    }
+    let value = match probe.average() {
+        Some(average) => format!(
+            "Input latency: {:.1}ms (last {})",
+            average.as_secs_f64() * 1000.0,
+            probe.samples.len()
+        ),
+        None => "Input latency: press a move key".to_string(),
+    };
+    let hud = HudText {
+        value,
+        color: Color::WHITE,
+        font_size: 16.0,
+        position: UiRect {
+            left: Val::Px(10.0),
+            top: Val::Px(10.0),
+            ..Default::default()
+        },
+    };
+    let existing = texts.get_single_mut().ok().map(|(_, text)| text);
+    show_hud_text(existing, hud, LatencyText, &asset_server, &mut commands);
+}
@@ -1,64 +1,229 @@
+// Bevy systems take their resources and queries as arguments
+#![allow(clippy::too_many_arguments, clippy::type_complexity)]
+
 use std::cmp::Ordering;
 use std::time::Duration;
 
-use bevy::app::App;
+use ai::{ai_player, AiControlled};
+use background::{
+    apply_background, drop_missing_background_image, resize_background_image, Background,
+};
+use bevy::app::{App, AppExit};
+use bevy::log::warn;
 use bevy::prelude::{
-    AssetServer, BuildChildren, Camera2dBundle, Changed, ClearColor, Color, Commands, Component,
-    DespawnRecursiveExt, DetectChanges, Entity, Input, KeyCode, NodeBundle, Query, Res, ResMut,
-    State, SystemSet, TextBundle, Transform, Vec3, With,
+    Added, AssetServer, BuildChildren, Camera2dBundle, ChangeTrackers, ClearColor, Color, Commands,
+    Component, CoreStage, DespawnRecursiveExt, DetectChanges, Entity, EventWriter, Input, KeyCode,
+    Local, NodeBundle, ParallelSystemDescriptorCoercion, Query, RemovedComponents, Res, ResMut,
+    State, SystemLabel, SystemSet, TextBundle, Transform, Vec2, Vec3, Visibility, With, Without,
 };
+use bevy::render::texture::ImageSettings;
 use bevy::sprite::{Sprite, SpriteBundle};
 use bevy::text::{TextAlignment, TextStyle};
 use bevy::time::{Time, Timer};
+use bevy::transform::TransformSystem;
 use bevy::ui::{AlignItems, JustifyContent, PositionType, Size, Style, UiColor, Val};
 use bevy::window::WindowDescriptor;
 use bevy::DefaultPlugins;
+use blitz::{render_blitz_clock, run_blitz};
+use board_style::{cycle_background_style, update_board_pattern, BackgroundStyle};
+use camera::{adjust_camera, apply_camera, CameraSettings};
+use ceiling::{lower_spawned_piece, HardCeiling};
+use column_stats::{update_column_stats, ColumnStatsOverlay};
+use display::{
+    apply_present_mode, change_display_settings, limit_frame_rate, load_battery_saver,
+    DisplaySettings,
+};
+use export::{export_board, export_stats_csv};
+use garbage::{
+    add_garbage_lines, refill_cheese, render_garbage_meter, render_goal_counter, versus_garbage,
+    GarbageQueue, GarbageRows, MAX_CHEESE_ROWS,
+};
+use gravity::{settle, GravityMode};
+use grounded::update_grounded_markers;
+use heatmap::{apply_heatmap, toggle_heatmap, HeatmapView};
+use input_log::{export_input_log, record_inputs, InputLog};
+use invisible::{animate_vanish, peek, start_vanishing, InvisibleMode, Peek};
+use latency::{finish_latency_sample, show_latency, LatencyProbe, LatencyTest};
+use mirror::{mirror_stack_on_clear, MirrorOnClear};
+use opacity::{
+    adjust_opacity, apply_background_opacity, apply_tile_opacity, BackgroundOpacity, TileOpacity,
+};
+use outline::{toggle_outline, update_outline, ActivePieceOutline};
+use puzzle::{end_puzzle, Puzzle};
+use rainbow::{apply_rainbow, toggle_rainbow, RainbowMode};
 use rand::rngs::SmallRng;
 use rand::SeedableRng;
-use tetrominoes::Tetromino;
+use stats::{
+    record_session_stats, reset_session_stats, show_downstack_efficiency, track_play_time,
+    track_recent_pieces, LinesCleared, PiecesPlaced, PlayTime, RecentPieces, SessionStats,
+};
+use stress::StressTestPlugin;
+use tetrominoes::{Piece, PieceSet, PieceSource};
+use trail::{fade_move_trail, record_move_trail, toggle_move_trail, MoveTrail};
+use window::{set_window_icon, update_window_title};
+use wrap::{update_wrap_indicator, WrapMode};
 
+mod ai;
+mod background;
+mod blitz;
+mod board_style;
+mod camera;
+mod ceiling;
+mod column_stats;
+mod display;
+mod export;
+mod garbage;
+mod gravity;
+mod grounded;
+mod heatmap;
+mod hud;
+mod input_log;
+mod invisible;
+mod latency;
+mod mirror;
+mod opacity;
+mod outline;
+mod puzzle;
+mod rainbow;
+mod stats;
+mod storage;
+mod stress;
+#[cfg(test)]
+mod testing;
 mod tetrominoes;
+mod trail;
+mod window;
+mod wrap;
 
-const CELL_SIZE: usize = 30;
 const ROWS: usize = 20;
 const COLUMNS: usize = 10;
-const GRID_START_X: f32 = -((COLUMNS * CELL_SIZE) as f32) / 2.0;
-const GRID_START_Y: f32 = -((ROWS * CELL_SIZE) as f32) / 2.0;
+/// Rows above the board that pieces spawn into. Tall enough for a standing
+/// five-cell I lifted clear of the stack, so it can lock above the board.
+const BUFFER_ROWS: usize = 5;
 const BACKGROUND: Color = Color::GRAY;
 const GRID_BACKGROUND: Color = Color::BLACK;
+const DANGER_LINE: Color = Color::rgba(1.0, 0.0, 0.0, 0.6);
+const CURTAIN: Color = Color::GRAY;
+
+// Draw order, back to front. The camera sees z from -0.1 to 999.9.
+const Z_BACKGROUND: f32 = 0.0;
+const Z_GRID: f32 = 0.25;
+const Z_GRID_PATTERN: f32 = 0.3;
+const Z_DANGER_LINE: f32 = 0.5;
+const Z_TRAIL: f32 = 0.75;
+const Z_STACK: f32 = 1.0;
+const Z_SPAWN_FLASH: f32 = 1.25;
+const Z_FALLING: f32 = 1.5;
+const Z_OUTLINE: f32 = 1.6;
+const Z_CURTAIN: f32 = 1.75;
+const Z_BUFFER_MASK: f32 = 1.9;
+const Z_OVERLAY: f32 = 2.0;
 
 pub struct Score(u32);
 
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum GameMode {
+    Marathon,
+    /// Downstacking practice: the board starts with `rows` rows of garbage,
+    /// and is refilled whenever they've all been cleared.
+    Cheese {
+        rows: u32,
+    },
+    /// Versus practice: garbage is sent every few seconds and rises
+    /// unless it's cancelled by clearing lines first.
+    Versus,
+    /// A board to clear with a fixed set of pieces, loaded from a file.
+    /// The game ends once they've all been placed.
+    Puzzle(Puzzle),
+    /// A two-minute score attack, with gravity speeding up every 30 seconds
+    Blitz,
+}
+
+impl GameMode {
+    fn name(&self) -> &'static str {
+        match self {
+            GameMode::Marathon => "Marathon",
+            GameMode::Cheese { .. } => "Cheese",
+            GameMode::Versus => "Versus",
+            GameMode::Puzzle(_) => "Puzzle",
+            GameMode::Blitz => "Blitz",
+        }
+    }
+
+    /// Picks the mode from the command line, e.g. `tetris-bevy cheese 9`
+    fn from_args() -> GameMode {
+        let args: Vec<String> = std::env::args().skip(1).collect();
+        match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+            [] => GameMode::Marathon,
+            ["cheese"] => GameMode::Cheese { rows: 9 },
+            ["versus"] => GameMode::Versus,
+            ["blitz"] => GameMode::Blitz,
+            ["puzzle", path] => match Puzzle::from_file(path) {
+                Ok(puzzle) => GameMode::Puzzle(puzzle),
+                Err(err) => panic!("Couldn't read the puzzle from {}: {}", path, err),
+            },
+            ["cheese", rows] => match rows.parse() {
+                Ok(rows) if (1..=MAX_CHEESE_ROWS).contains(&rows) => GameMode::Cheese { rows },
+                _ => panic!("Cheese rows must be a number from 1 to {}", MAX_CHEESE_ROWS),
+            },
+            _ => panic!("Usage: tetris-bevy [cheese [rows] | versus | blitz | puzzle <file>]"),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
 enum GameState {
+    /// The board filling up from the bottom after a loss
+    GameOverAnimation,
     GameOver,
     Playing,
 }
 
-#[derive(Component, Clone, Copy, PartialEq, Eq)]
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
 struct Tile {
     x: i8,
     y: i8,
 }
 
+/// The point a piece turns about, relative to its focal point
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Pivot {
+    /// The focal point itself
+    Cell,
+    /// Half a cell above and to the right of the focal point,
+    /// for pieces like I and O whose center lies between cells
+    Corner,
+}
+
 #[derive(Component, Clone, Copy, PartialEq, Eq)]
 struct FallingSegment {
     x_offset: i8,
     y_offset: i8,
+    pivot: Pivot,
 }
 
 impl FallingSegment {
     fn rotate_clockwise(self) -> FallingSegment {
+        let (x_offset, y_offset) = match self.pivot {
+            Pivot::Cell => (self.y_offset, -self.x_offset),
+            Pivot::Corner => (self.y_offset, 1 - self.x_offset),
+        };
         FallingSegment {
-            x_offset: self.y_offset,
-            y_offset: -self.x_offset,
+            x_offset,
+            y_offset,
+            ..self
         }
     }
 
     fn rotate_counterclockwise(self) -> FallingSegment {
+        let (x_offset, y_offset) = match self.pivot {
+            Pivot::Cell => (-self.y_offset, self.x_offset),
+            Pivot::Corner => (1 - self.y_offset, self.x_offset),
+        };
         FallingSegment {
-            x_offset: -self.y_offset,
-            y_offset: self.x_offset,
+            x_offset,
+            y_offset,
+            ..self
         }
     }
 }
@@ -66,108 +231,474 @@ impl FallingSegment {
 #[derive(Component)]
 struct GameOverText;
 
+#[derive(Component)]
+struct GridBackground;
+
+/// Covers the rows above the board, so pieces appear from behind the top edge
+#[derive(Component)]
+struct BufferMask;
+
+/// The row whose bottom edge the danger line is drawn along
+struct DangerLineRow(usize);
+
+struct DangerLineVisible(bool);
+
+#[derive(Component)]
+struct DangerLine;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BoardSize {
+    Small,
+    Medium,
+    Large,
+}
+
+impl BoardSize {
+    /// The side length of a cell in pixels
+    fn cell_size(self) -> f32 {
+        match self {
+            BoardSize::Small => 24.0,
+            BoardSize::Medium => 30.0,
+            BoardSize::Large => 34.0,
+        }
+    }
+
+    fn next(self) -> BoardSize {
+        match self {
+            BoardSize::Small => BoardSize::Medium,
+            BoardSize::Medium => BoardSize::Large,
+            BoardSize::Large => BoardSize::Small,
+        }
+    }
+}
+
+/// Where and how big the board is drawn, relative to the center of the window
+struct BoardLayout {
+    offset: Vec2,
+    size: BoardSize,
+}
+
+impl BoardLayout {
+    fn cell_size(&self) -> f32 {
+        self.size.cell_size()
+    }
+
+    /// The center of the cell at `(x, y)`, which may be fractional
+    fn cell_position(&self, x: f32, y: f32) -> Vec2 {
+        Vec2::new(
+            self.offset.x + (x - COLUMNS as f32 / 2.0) * self.cell_size(),
+            self.offset.y + (y - ROWS as f32 / 2.0) * self.cell_size(),
+        )
+    }
+}
+
 // Have some extra rows at the top in case a piece is placed above the screen
-struct FullGrid([[bool; COLUMNS]; ROWS + 4]);
+#[derive(Clone)]
+struct FullGrid([[bool; COLUMNS]; ROWS + BUFFER_ROWS]);
 impl FullGrid {
     fn empty() -> FullGrid {
-        FullGrid([[false; COLUMNS]; ROWS + 4])
+        FullGrid([[false; COLUMNS]; ROWS + BUFFER_ROWS])
     }
 }
 
-fn main() {
-    App::new()
-        .insert_resource(WindowDescriptor {
-            title: "Tetris".to_string(),
-            width: 500.0,
-            height: 700.0,
-            ..Default::default()
-        })
-        .insert_resource(ClearColor(BACKGROUND))
-        .add_plugins(DefaultPlugins)
-        .add_state(GameState::Playing)
-        .add_startup_system(setup)
-        .add_system_set(SystemSet::on_enter(GameState::Playing).with_system(start_game))
-        .add_system_set(
-            SystemSet::on_update(GameState::Playing)
-                .with_system(fall)
-                .with_system(handle_input)
-                .with_system(clear_rows)
-                .with_system(update_translation)
-                .with_system(check_loss),
+/// The steps of a frame that change the board. They run in this order, so that
+/// the same inputs on the same frames always play out the same.
+#[derive(SystemLabel, Debug, Clone, PartialEq, Eq, Hash)]
+enum BoardStep {
+    Spawn,
+    Garbage,
+    EntryDelay,
+    Input,
+    Fall,
+    Clear,
+    Loss,
+}
+
+fn board_systems(set: SystemSet) -> SystemSet {
+    set.with_system(lower_spawned_piece.label(BoardStep::Spawn))
+        .with_system(
+            refill_cheese
+                .label(BoardStep::Garbage)
+                .after(BoardStep::Spawn),
+        )
+        .with_system(
+            versus_garbage
+                .label(BoardStep::Garbage)
+                .after(BoardStep::Spawn)
+                .after(refill_cheese),
+        )
+        .with_system(
+            tick_entry_delay
+                .label(BoardStep::EntryDelay)
+                .after(BoardStep::Garbage),
         )
-        .add_system_set(SystemSet::on_enter(GameState::GameOver).with_system(show_gameover))
-        .add_system_set(SystemSet::on_update(GameState::GameOver).with_system(check_restart))
-        .add_system_set(SystemSet::on_exit(GameState::GameOver).with_system(hide_gameover))
-        .run();
+        .with_system(
+            ai_player
+                .label(BoardStep::Input)
+                .after(BoardStep::EntryDelay),
+        )
+        .with_system(
+            handle_input
+                .label(BoardStep::Input)
+                .after(BoardStep::EntryDelay)
+                .after(ai_player),
+        )
+        .with_system(
+            reset_piece_to_spawn
+                .label(BoardStep::Input)
+                .after(BoardStep::EntryDelay)
+                .after(handle_input),
+        )
+        .with_system(fall.label(BoardStep::Fall).after(BoardStep::Input))
+        // Clears on the frame a piece locks, before anything else moves in
+        .with_system(clear_rows.label(BoardStep::Clear).after(BoardStep::Fall))
+        .with_system(
+            mirror_stack_on_clear
+                .label(BoardStep::Clear)
+                .after(BoardStep::Fall)
+                .after(clear_rows),
+        )
+        .with_system(check_loss.label(BoardStep::Loss).after(BoardStep::Clear))
+        .with_system(
+            end_puzzle
+                .label(BoardStep::Loss)
+                .after(BoardStep::Clear)
+                .after(check_loss),
+        )
+}
+
+fn main() {
+    let display_settings = DisplaySettings {
+        vsync: true,
+        frame_cap: None,
+        battery_saver: std::env::var_os("TETRIS_BATTERY_SAVER").is_some() || load_battery_saver(),
+    };
+    let mut app = App::new();
+    app.insert_resource(WindowDescriptor {
+        title: "Tetris".to_string(),
+        width: 500.0,
+        height: 700.0,
+        present_mode: display_settings.present_mode(),
+        ..Default::default()
+    })
+    .insert_resource(display_settings)
+    .insert_resource(Background::from_env())
+    // Keep any textures crisp when the board is scaled
+    .insert_resource(ImageSettings::default_nearest())
+    .insert_resource(EntryDelay::from_env())
+    .insert_resource(SoftDropLocks(
+        std::env::var_os("TETRIS_SOFT_DROP_LOCKS").is_some(),
+    ))
+    .insert_resource(ScoringConfig::new(ScoringSystem::from_env()))
+    .insert_resource(GravityMode::from_env())
+    .insert_resource(GameMode::from_args())
+    .insert_resource(PieceSet::from_env())
+    .insert_resource(InvisibleMode(
+        std::env::var_os("TETRIS_INVISIBLE").is_some(),
+    ))
+    .insert_resource(Peek::default())
+    .insert_resource(WrapMode(std::env::var_os("TETRIS_WRAP").is_some()))
+    .insert_resource(HardCeiling(
+        std::env::var_os("TETRIS_HARD_CEILING").is_some(),
+    ))
+    .insert_resource(HeatmapView(false))
+    .insert_resource(MoveTrail(false))
+    .insert_resource(BackgroundOpacity(1.0))
+    .insert_resource(TileOpacity(1.0))
+    .insert_resource(RainbowMode(false))
+    .insert_resource(ActivePieceOutline(false))
+    .insert_resource(CameraSettings::load())
+    .insert_resource(BackgroundStyle::from_env())
+    .insert_resource(InputLog::default())
+    .insert_resource(PieceSequenceFile(std::env::var("TETRIS_PIECES").ok()))
+    .insert_resource(RngSeed::from_env())
+    .insert_resource(Deterministic(
+        std::env::var_os("TETRIS_DETERMINISTIC").is_some(),
+    ))
+    .insert_resource(CollapseAnimation(true))
+    .insert_resource(ReduceMotion(false))
+    .insert_resource(MirrorControls(false))
+    .insert_resource(MirrorOnClear(
+        std::env::var_os("TETRIS_MIRROR_ON_CLEAR").is_some(),
+    ))
+    .insert_resource(PracticeMode(false))
+    .insert_resource(ResetPieceKey(KeyCode::R))
+    .insert_resource(ColumnStatsOverlay(false))
+    .insert_resource(AiControlled(false))
+    .insert_resource(BoardLayout {
+        offset: Vec2::ZERO,
+        size: BoardSize::Medium,
+    })
+    .insert_resource(LatencyTest(false))
+    .insert_resource(DangerLineRow(ROWS - 4))
+    .insert_resource(DangerLineVisible(true))
+    .insert_resource(LatencyProbe::default())
+    .insert_resource(SessionStats::load())
+    .insert_resource(RecentPieces::default())
+    .add_plugins(DefaultPlugins)
+    .add_state(GameState::Playing)
+    .add_startup_system(setup)
+    .add_startup_system(set_window_icon)
+    .add_system_set(SystemSet::on_enter(GameState::Playing).with_system(start_game))
+    .add_system_set(board_systems(SystemSet::on_update(GameState::Playing)))
+    .add_system_set(
+        SystemSet::on_update(GameState::Playing)
+            .with_system(render_goal_counter)
+            .with_system(update_column_stats)
+            .with_system(record_move_trail.after(fall).after(handle_input))
+            .with_system(render_garbage_meter.after(versus_garbage))
+            .with_system(track_recent_pieces.after(fall).after(clear_rows))
+            .with_system(update_grounded_markers.after(fall).after(handle_input))
+            .with_system(track_play_time)
+            .with_system(run_blitz.after(track_play_time).after(check_loss))
+            .with_system(render_blitz_clock.after(track_play_time)),
+    )
+    .add_system(animate_collapse)
+    .add_system(toggle_settings)
+    .add_system(quit)
+    .add_system(apply_background)
+    .add_system(resize_background_image)
+    .add_system(drop_missing_background_image)
+    .add_system(reset_session_stats)
+    .add_system(change_display_settings)
+    .add_system(apply_present_mode.after(change_display_settings))
+    .add_system_to_stage(CoreStage::Last, limit_frame_rate)
+    // Bevy's own clock always updates first
+    .add_system_to_stage(CoreStage::First, apply_fixed_step)
+    .add_system_to_stage(CoreStage::First, finish_latency_sample)
+    .add_system(show_latency)
+    .add_system(show_downstack_efficiency)
+    .add_system(peek)
+    .add_system(toggle_heatmap)
+    .add_system(toggle_move_trail)
+    .add_system(adjust_opacity)
+    .add_system(toggle_rainbow)
+    .add_system(toggle_outline)
+    .add_system(adjust_camera)
+    .add_system(cycle_background_style)
+    .add_system(
+        update_board_pattern
+            .after(cycle_background_style)
+            .after(adjust_opacity),
+    )
+    .add_system(apply_camera.after(adjust_camera))
+    // Outside the playing state, so the outline is cleared when the game ends
+    .add_system(
+        update_outline
+            .after(toggle_outline)
+            .after(fall)
+            .after(handle_input),
+    )
+    .add_system(record_inputs)
+    .add_system(update_window_title)
+    .add_system(export_board)
+    .add_system(export_input_log.after(record_inputs))
+    .add_system(apply_background_opacity)
+    .add_system(fade_move_trail)
+    // Sync sprites after the game logic's commands have been applied
+    .add_system_to_stage(
+        CoreStage::PostUpdate,
+        update_translation.before(TransformSystem::TransformPropagate),
+    )
+    .add_system_to_stage(
+        CoreStage::PostUpdate,
+        animate_spawn_flash.before(TransformSystem::TransformPropagate),
+    )
+    // Tile colors are set one after another, each keeping the alpha it finds,
+    // and then the opacity, fades and vanishing set the alpha last
+    .add_system_set_to_stage(
+        CoreStage::PostUpdate,
+        SystemSet::new()
+            .with_system(record_base_color)
+            .with_system(animate_lock_flash.after(record_base_color))
+            .with_system(apply_heatmap.after(animate_lock_flash))
+            // Wins over the heatmap, since it recolors every tile
+            .with_system(apply_rainbow.after(apply_heatmap))
+            .with_system(apply_tile_opacity.after(apply_rainbow))
+            // Runs on the frame a piece spawns, so it never shows at full opacity first
+            .with_system(animate_spawn_fade.after(apply_tile_opacity))
+            .with_system(start_vanishing.after(animate_spawn_fade))
+            .with_system(animate_vanish.after(start_vanishing)),
+    )
+    .add_system_to_stage(
+        CoreStage::PostUpdate,
+        move_locked_tiles_to_stack.before(TransformSystem::TransformPropagate),
+    )
+    .add_system_to_stage(
+        CoreStage::PostUpdate,
+        update_grid_background.before(TransformSystem::TransformPropagate),
+    )
+    .add_system_to_stage(
+        CoreStage::PostUpdate,
+        update_danger_line.before(TransformSystem::TransformPropagate),
+    )
+    .add_system_to_stage(
+        CoreStage::PostUpdate,
+        update_buffer_mask.before(TransformSystem::TransformPropagate),
+    )
+    .add_system_to_stage(
+        CoreStage::PostUpdate,
+        update_wrap_indicator.before(TransformSystem::TransformPropagate),
+    )
+    .add_system_set(
+        SystemSet::on_enter(GameState::GameOver)
+            .with_system(record_session_stats.before(show_gameover))
+            .with_system(export_stats_csv)
+            .with_system(show_gameover),
+    )
+    .add_system_set(SystemSet::on_enter(GameState::GameOverAnimation).with_system(start_curtain))
+    .add_system_set(SystemSet::on_update(GameState::GameOverAnimation).with_system(fill_curtain))
+    .add_system_set(SystemSet::on_update(GameState::GameOver).with_system(check_restart))
+    .add_system_set(SystemSet::on_exit(GameState::GameOver).with_system(hide_gameover));
+    if std::env::var_os("TETRIS_STRESS").is_some() {
+        app.add_plugin(StressTestPlugin);
+    }
+    app.run();
+}
+
+/// A tile's own color, for restoring after a view that recolors tiles is turned off
+#[derive(Component)]
+struct BaseColor(Color);
+
+fn record_base_color(tiles: Query<(Entity, &Sprite), Added<Tile>>, mut commands: Commands) {
+    for (entity, sprite) in &tiles {
+        commands.entity(entity).insert(BaseColor(sprite.color));
+    }
 }
 
-fn tile_sprite(x: i8, y: i8, color: Color) -> SpriteBundle {
+/// The sprite for a tile, which `update_translation` positions
+/// once the `Tile` component is added alongside it
+fn tile_sprite(color: Color) -> SpriteBundle {
     SpriteBundle {
         sprite: Sprite {
             color,
             ..Default::default()
         },
-        transform: Transform {
-            translation: Vec3::new(
-                GRID_START_X + x as f32 * CELL_SIZE as f32,
-                GRID_START_Y + y as f32 * CELL_SIZE as f32,
-                0.0,
-            ),
-            scale: Vec3::new(CELL_SIZE as f32, CELL_SIZE as f32, 0.0),
-            ..Default::default()
-        },
+        transform: Transform::from_xyz(0.0, 0.0, Z_STACK),
         ..Default::default()
     }
 }
 
-fn setup(mut commands: Commands) {
-    // Camera
+fn setup(
+    mut commands: Commands,
+    layout: Res<BoardLayout>,
+    danger_line_row: Res<DangerLineRow>,
+    danger_line_visible: Res<DangerLineVisible>,
+) {
+    // Camera. Its default projection is one world unit per pixel both ways,
+    // so sprites stay square whatever the window's aspect ratio.
     commands.spawn_bundle(Camera2dBundle::default());
 
     // Grid background
-    let width = COLUMNS as f32 * CELL_SIZE as f32;
-    let height = ROWS as f32 * CELL_SIZE as f32;
-    commands.spawn_bundle(SpriteBundle {
-        sprite: Sprite {
-            color: GRID_BACKGROUND,
+    commands
+        .spawn_bundle(SpriteBundle {
+            sprite: Sprite {
+                color: GRID_BACKGROUND,
+                ..Default::default()
+            },
+            transform: grid_background_transform(&layout),
             ..Default::default()
-        },
-        transform: Transform {
-            translation: Vec3::new(
-                GRID_START_X + (width / 2.0) - (CELL_SIZE as f32 / 2.0),
-                GRID_START_Y + (height / 2.0) - (CELL_SIZE as f32 / 2.0),
-                0.0,
-            ),
-            scale: Vec3::new(width, height, 0.0),
+        })
+        .insert(GridBackground);
+
+    // Danger line
+    commands
+        .spawn_bundle(SpriteBundle {
+            sprite: Sprite {
+                color: DANGER_LINE,
+                ..Default::default()
+            },
+            transform: danger_line_transform(&layout, danger_line_row.0),
+            visibility: Visibility {
+                is_visible: danger_line_visible.0,
+            },
             ..Default::default()
-        },
-        ..Default::default()
-    });
+        })
+        .insert(DangerLine);
+
+    // Buffer mask
+    commands
+        .spawn_bundle(SpriteBundle {
+            sprite: Sprite {
+                color: BACKGROUND,
+                ..Default::default()
+            },
+            transform: buffer_mask_transform(&layout),
+            ..Default::default()
+        })
+        .insert(BufferMask);
 
     // Rng
     commands.insert_resource(SmallRng::from_entropy());
 }
 
+/// Resets everything that belongs to a single game. Settings and the game mode
+/// are left alone, so restarting plays the same mode again.
 fn start_game(
     mut commands: Commands,
+    mode: Res<GameMode>,
+    sequence_file: Res<PieceSequenceFile>,
+    piece_set: Res<PieceSet>,
+    seed: Res<RngSeed>,
     mut rng: ResMut<SmallRng>,
     mut keyboard_input: ResMut<Input<KeyCode>>,
     tiles: Query<Entity, With<Tile>>,
 ) {
     keyboard_input.reset_all();
+    if let Some(seed) = seed.0 {
+        *rng = SmallRng::seed_from_u64(seed);
+    }
     tiles.for_each(|entity| commands.entity(entity).despawn_recursive());
 
-    commands.insert_resource(FallTimer(Timer::new(
-        Duration::from_secs_f32(1.0 / 5.0),
-        true,
-    )));
-    commands.insert_resource(FullGrid::empty());
+    commands.insert_resource(FallTimer(Timer::new(FALL_INTERVAL, true)));
+    commands.insert_resource(NaturalFall {
+        timer: Timer::new(FALL_INTERVAL, true),
+        steps: 0,
+    });
     commands.insert_resource(Score(0));
+    commands.insert_resource(LinesCleared(0));
+    commands.insert_resource(PiecesPlaced(0));
+    commands.insert_resource(PlayTime(Duration::ZERO));
+    commands.insert_resource(EntryTimer(None));
+    commands.insert_resource(BufferedInput::default());
+    commands.insert_resource(GarbageQueue::new());
+    commands.insert_resource(RecentPieces::default());
 
-    spawn(&mut commands, &mut rng);
+    let mut full_grid = FullGrid::empty();
+    let mut garbage_rows = GarbageRows(0);
+    if let GameMode::Cheese { rows } = *mode {
+        add_garbage_lines(
+            rows as usize,
+            &mut full_grid,
+            &mut garbage_rows,
+            std::iter::empty(),
+            &mut commands,
+            &mut rng,
+        );
+    }
+    let mut piece_source = match (&*mode, &sequence_file.0) {
+        (GameMode::Puzzle(puzzle), _) => puzzle.set_up(&mut full_grid, &mut commands),
+        (_, Some(path)) => PieceSource::from_file(path, *piece_set).unwrap_or_else(|err| {
+            warn!("Couldn't read the piece sequence from {}: {}", path, err);
+            PieceSource::Randomizer(*piece_set)
+        }),
+        (_, None) => PieceSource::Randomizer(*piece_set),
+    };
+    commands.insert_resource(full_grid);
+    commands.insert_resource(garbage_rows);
+
+    if let Some(piece) = piece_source.next(&mut rng) {
+        spawn(&mut commands, piece);
+    }
+    commands.insert_resource(piece_source);
 }
 
-fn show_gameover(score: Res<Score>, asset_server: Res<AssetServer>, mut commands: Commands) {
+fn show_gameover(
+    score: Res<Score>,
+    session: Res<SessionStats>,
+    asset_server: Res<AssetServer>,
+    mut commands: Commands,
+) {
     commands
         .spawn_bundle(NodeBundle {
             style: Style {
@@ -195,6 +726,23 @@ fn show_gameover(score: Res<Score>, asset_server: Res<AssetServer>, mut commands
                 )
                 .with_text_alignment(TextAlignment::CENTER),
             );
+            parent.spawn_bundle(
+                TextBundle::from_section(
+                    format!(
+                        "All games: {} played, {} lines, {} pieces, best score {}",
+                        session.games_played,
+                        session.total_lines,
+                        session.total_pieces,
+                        session.best_score
+                    ),
+                    TextStyle {
+                        font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                        font_size: 20.0,
+                        color: Color::WHITE,
+                    },
+                )
+                .with_text_alignment(TextAlignment::CENTER),
+            );
         })
         .insert(GameOverText);
 }
@@ -209,35 +757,326 @@ fn check_restart(keyboard_input: Res<Input<KeyCode>>, mut game_state: ResMut<Sta
     }
 }
 
+/// How long a piece takes to fall one row without soft drop
+const FALL_INTERVAL: Duration = Duration::from_millis(200);
+
 struct FallTimer(Timer);
 
+/// Follows the unsped fall rate, so soft drop only scores
+/// the rows a piece falls beyond what gravity would have moved it
+struct NaturalFall {
+    timer: Timer,
+    /// Rows gravity has moved the piece that haven't been taken yet
+    steps: u32,
+}
+
+/// A handicap that swaps the left and right keys and the two rotation keys
+struct MirrorControls(bool);
+
+/// Lets the falling piece be sent back to where it spawned to retry a placement
+struct PracticeMode(bool);
+
+/// The key that sends the falling piece back to its spawn in practice mode
+struct ResetPieceKey(KeyCode);
+
+/// Where a falling segment spawned, for resetting it in practice mode
+#[derive(Component)]
+struct SpawnState {
+    tile: Tile,
+    segment: FallingSegment,
+}
+
+/// Turns off purely cosmetic motion, snapping everything straight into place
+struct ReduceMotion(bool);
+
+const COLLAPSE_DURATION: Duration = Duration::from_millis(150);
+
+/// Whether rows above a line clear slide down instead of snapping into place
+struct CollapseAnimation(bool);
+
+const SPAWN_FADE_DURATION: Duration = Duration::from_millis(100);
+
+/// Fades in a newly spawned piece's sprites
+#[derive(Component)]
+struct SpawnFade(Timer);
+
+const SPAWN_FLASH_DURATION: Duration = Duration::from_millis(250);
+const SPAWN_FLASH: Color = Color::rgba(1.0, 1.0, 1.0, 0.35);
+
+/// A faint highlight on a cell at the top of the board, where a piece spawning
+/// in the buffer rows is about to come into view
+#[derive(Component)]
+struct SpawnFlash {
+    cell: Tile,
+    timer: Timer,
+}
+
+const LOCK_FLASH_DURATION: Duration = Duration::from_millis(120);
+
+/// Flashes a just-locked tile white, fading back to its own color
+#[derive(Component)]
+struct LockFlash {
+    timer: Timer,
+    /// The tile's own color, taken once the flash starts
+    color: Option<Color>,
+}
+
+/// Slides a tile's sprite down to its row after the rows beneath it were cleared
+#[derive(Component)]
+struct Collapse(Timer);
+
+/// A file of pieces to deal in order before falling back to the randomizer
+struct PieceSequenceFile(Option<String>);
+
+/// Seeds the random number generator at the start of every game, so that the
+/// pieces and garbage holes come out the same each time
+struct RngSeed(Option<u64>);
+
+impl RngSeed {
+    /// Reads the seed from the `TETRIS_SEED` environment variable, if it's set
+    fn from_env() -> RngSeed {
+        let seed = match std::env::var("TETRIS_SEED") {
+            Ok(seed) => seed,
+            Err(_) => return RngSeed(None),
+        };
+        RngSeed(match seed.parse() {
+            Ok(seed) => Some(seed),
+            Err(err) => {
+                warn!("Ignoring TETRIS_SEED {:?}: {}", seed, err);
+                None
+            }
+        })
+    }
+}
+
+/// How long every frame counts as in deterministic mode, whatever it really took
+const FIXED_STEP: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+/// Advances the game by a fixed step each frame instead of by the frame's real length,
+/// so that with `TETRIS_SEED` the same inputs on the same frames always play out the
+/// same. Enabled by setting the `TETRIS_DETERMINISTIC` environment variable.
+struct Deterministic(bool);
+
+/// Replaces the frame's real time with the fixed step, in deterministic mode
+fn apply_fixed_step(
+    deterministic: Res<Deterministic>,
+    mut time: ResMut<Time>,
+    mut clock: Local<Option<Time>>,
+) {
+    if !deterministic.0 {
+        return;
+    }
+    let clock = clock.get_or_insert_with(|| time.clone());
+    let now = clock.last_update().unwrap_or_else(|| clock.startup()) + FIXED_STEP;
+    clock.update_with_instant(now);
+    *time = clock.clone();
+}
+
+/// Whether pressing soft drop while the piece is resting on the stack locks it immediately
+struct SoftDropLocks(bool);
+
+/// How long to wait between a piece locking and the next one spawning (ARE)
+struct EntryDelay(Duration);
+
+impl EntryDelay {
+    /// Reads the delay in milliseconds from `TETRIS_ENTRY_DELAY`, or none if it isn't set
+    fn from_env() -> EntryDelay {
+        let millis = match std::env::var("TETRIS_ENTRY_DELAY") {
+            Ok(millis) => millis,
+            Err(_) => return EntryDelay(Duration::ZERO),
+        };
+        EntryDelay(match millis.parse() {
+            Ok(millis) => Duration::from_millis(millis),
+            Err(err) => {
+                warn!("Ignoring TETRIS_ENTRY_DELAY {:?}: {}", millis, err);
+                Duration::ZERO
+            }
+        })
+    }
+}
+
+/// Counts down the entry delay, if one is in progress
+struct EntryTimer(Option<Timer>);
+
+/// Moves waiting to be applied to the falling piece,
+/// either pressed during the entry delay or issued by the AI
+#[derive(Default)]
+struct BufferedInput {
+    left: bool,
+    right: bool,
+    z: bool,
+    x: bool,
+    a: bool,
+}
+
 fn is_full(x: i8, y: i8, full_grid: &FullGrid) -> bool {
     y < ROWS as i8 && full_grid.0[usize::try_from(y).unwrap()][usize::try_from(x).unwrap()]
 }
 
-fn can_fit(mut segments: impl Iterator<Item = Tile>, full_grid: &FullGrid) -> bool {
-    segments.all(|Tile { x, y }| in_bounds(x, y) && !is_full(x, y, full_grid))
+fn fits_at(
+    tiles: impl IntoIterator<Item = Tile>,
+    hard_ceiling: bool,
+    full_grid: &FullGrid,
+) -> bool {
+    tiles
+        .into_iter()
+        .all(|Tile { x, y }| in_bounds(x, y, hard_ceiling) && !is_full(x, y, full_grid))
+}
+
+/// Brings a column that's past one side of the board back in on the other, in wrap mode
+fn wrap_column(x: i8, wrap: bool) -> i8 {
+    if wrap {
+        x.rem_euclid(COLUMNS as i8)
+    } else {
+        x
+    }
+}
+
+/// The piece shifted `dx` columns, if it fits there
+fn try_move(
+    piece: &[(Tile, FallingSegment)],
+    dx: i8,
+    wrap: bool,
+    hard_ceiling: bool,
+    full_grid: &FullGrid,
+) -> Option<Vec<(Tile, FallingSegment)>> {
+    let moved: Vec<_> = piece
+        .iter()
+        .map(|&(tile, segment)| {
+            (
+                Tile {
+                    x: wrap_column(tile.x + dx, wrap),
+                    ..tile
+                },
+                segment,
+            )
+        })
+        .collect();
+    fits_at(moved.iter().map(|&(tile, _)| tile), hard_ceiling, full_grid).then_some(moved)
 }
 
-fn can_fall(segments: impl Iterator<Item = Tile>, full_grid: &FullGrid) -> bool {
-    can_fit(
+/// The piece turned about its focal point, if it fits there.
+/// There's no kick table, so a rotation that doesn't fit in place fails.
+fn try_rotate(
+    piece: &[(Tile, FallingSegment)],
+    rotation: fn(FallingSegment) -> FallingSegment,
+    wrap: bool,
+    hard_ceiling: bool,
+    full_grid: &FullGrid,
+) -> Option<Vec<(Tile, FallingSegment)>> {
+    let rotated: Vec<_> = piece
+        .iter()
+        .map(|&(tile, segment)| {
+            let new_segment = rotation(segment);
+            // Each cell turns relative to its own column, which is consistent modulo
+            // the board width even when a wrapped piece straddles the edges
+            let new_tile = Tile {
+                x: wrap_column(tile.x - segment.x_offset + new_segment.x_offset, wrap),
+                y: tile.y - segment.y_offset + new_segment.y_offset,
+            };
+            (new_tile, new_segment)
+        })
+        .collect();
+    fits_at(
+        rotated.iter().map(|&(tile, _)| tile),
+        hard_ceiling,
+        full_grid,
+    )
+    .then_some(rotated)
+}
+
+/// How many rows the tiles can fall before they land
+fn compute_drop_distance(tiles: &[Tile], hard_ceiling: bool, full_grid: &FullGrid) -> i8 {
+    let mut distance = 0;
+    while fits_at(
+        tiles.iter().map(|&tile| Tile {
+            y: tile.y - distance - 1,
+            ..tile
+        }),
+        hard_ceiling,
+        full_grid,
+    ) {
+        distance += 1;
+    }
+    distance
+}
+
+fn can_fall(
+    segments: impl Iterator<Item = Tile>,
+    hard_ceiling: bool,
+    full_grid: &FullGrid,
+) -> bool {
+    fits_at(
         segments.map(|Tile { x, y }| Tile { x, y: y - 1 }),
+        hard_ceiling,
         full_grid,
     )
 }
 
-fn spawn(commands: &mut Commands, rng: &mut SmallRng) {
+fn spawn(commands: &mut Commands, piece: Piece) {
     let focal_x = 6;
-    let focal_y = ROWS;
-    let tetromino = Tetromino::random(rng);
-    for segment in tetromino.shape {
+    // Puts the piece's lowest cells on the first row above the screen
+    let lowest = piece.shape.iter().map(|segment| segment.y_offset).min();
+    let focal_y = ROWS as i8 - lowest.unwrap_or(0);
+    let highest = piece.shape.iter().map(|segment| segment.y_offset).max();
+    // The highlight shows the piece's footprint in the top rows of the board
+    let height = highest.unwrap_or(0) - lowest.unwrap_or(0) + 1;
+    for &segment in piece.shape {
         let x = (focal_x as i8) + segment.x_offset;
-        let y = (focal_y as i8) + segment.y_offset;
+        let y = focal_y + segment.y_offset;
+        let mut sprite = tile_sprite(piece.color);
+        sprite.transform.translation.z = Z_FALLING;
         commands
             .spawn()
-            .insert_bundle(tile_sprite(x, y, tetromino.color))
+            .insert_bundle(sprite)
             .insert(Tile { x, y })
-            .insert(segment);
+            .insert(segment)
+            .insert(SpawnState {
+                tile: Tile { x, y },
+                segment,
+            })
+            .insert(SpawnFade(Timer::new(SPAWN_FADE_DURATION, false)));
+        commands
+            .spawn_bundle(SpriteBundle {
+                sprite: Sprite {
+                    color: SPAWN_FLASH,
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .insert(SpawnFlash {
+                cell: Tile { x, y: y - height },
+                timer: Timer::new(SPAWN_FLASH_DURATION, false),
+            });
+    }
+}
+
+fn lock_and_spawn(
+    segments: impl Iterator<Item = (Entity, Tile)>,
+    commands: &mut Commands,
+    full_grid: &mut FullGrid,
+    entry_delay: &EntryDelay,
+    entry_timer: &mut EntryTimer,
+    pieces: &mut PieceSource,
+    rng: &mut SmallRng,
+) {
+    for (entity, tile) in segments {
+        commands
+            .entity(entity)
+            .remove::<FallingSegment>()
+            .remove::<SpawnState>()
+            .insert(LockFlash {
+                timer: Timer::new(LOCK_FLASH_DURATION, false),
+                color: None,
+            });
+        full_grid.0[usize::try_from(tile.y).unwrap()][usize::try_from(tile.x).unwrap()] = true;
+    }
+    if entry_delay.0.is_zero() {
+        if let Some(piece) = pieces.next(rng) {
+            spawn(commands, piece);
+        }
+    } else {
+        entry_timer.0 = Some(Timer::new(entry_delay.0, false));
     }
 }
 
@@ -245,157 +1084,1325 @@ fn fall(
     time: Res<Time>,
     mut rng: ResMut<SmallRng>,
     mut timer: ResMut<FallTimer>,
+    mut natural_fall: ResMut<NaturalFall>,
     mut segment_ents: Query<(Entity, &mut Tile, &FallingSegment)>,
     mut commands: Commands,
     mut full_grid: ResMut<FullGrid>,
+    entry_delay: Res<EntryDelay>,
+    mut entry_timer: ResMut<EntryTimer>,
+    mut piece_source: ResMut<PieceSource>,
+    mut pieces: ResMut<PiecesPlaced>,
+    keyboard_input: Res<Input<KeyCode>>,
+    soft_drop_locks: Res<SoftDropLocks>,
+    mut score: ResMut<Score>,
+    scoring: Res<ScoringConfig>,
+    hard_ceiling: Res<HardCeiling>,
 ) {
-    let times = timer.0.tick(time.delta()).times_finished_this_tick();
+    let soft_drop = !alt_held(&keyboard_input) && keyboard_input.pressed(KeyCode::Down);
+    // After a hitch, fall one row rather than jumping several rows in a single frame.
+    // The repeating timer keeps only the leftover time, so the skipped steps don't pile up.
+    let mut times = timer.0.tick(time.delta()).times_finished_this_tick().min(1);
+    // Capped so that gravity owed while the piece was resting isn't banked
+    let natural_times = natural_fall
+        .timer
+        .tick(time.delta())
+        .times_finished_this_tick();
+    natural_fall.steps = (natural_fall.steps + natural_times).min(1);
+    if soft_drop_locks.0
+        && soft_drop
+        && keyboard_input.just_pressed(KeyCode::Down)
+        && !segment_ents.is_empty()
+        && !can_fall(
+            segment_ents.iter().map(|(_, t, _)| *t),
+            hard_ceiling.0,
+            &full_grid,
+        )
+    {
+        // A grounded piece's next fall step locks it, so take that step now
+        times = times.max(1);
+    }
     for _ in 0..times {
-        if can_fall(segment_ents.iter().map(|(_, t, _)| *t), &full_grid) {
+        if segment_ents.is_empty() {
+            // Waiting out the entry delay
+            break;
+        }
+        if can_fall(
+            segment_ents.iter().map(|(_, t, _)| *t),
+            hard_ceiling.0,
+            &full_grid,
+        ) {
             for (_, mut tile, _) in &mut segment_ents {
                 tile.y -= 1;
             }
+            if natural_fall.steps > 0 {
+                natural_fall.steps -= 1;
+            } else if soft_drop {
+                score.0 += scoring.soft_drop_per_cell;
+            }
         } else {
-            for (entity, tile, _) in &segment_ents {
-                commands.entity(entity).remove::<FallingSegment>();
-                full_grid.0[usize::try_from(tile.y).unwrap()][usize::try_from(tile.x).unwrap()] =
-                    true;
+            pieces.0 += 1;
+            lock_and_spawn(
+                segment_ents.iter().map(|(entity, tile, _)| (entity, *tile)),
+                &mut commands,
+                &mut full_grid,
+                &entry_delay,
+                &mut entry_timer,
+                &mut piece_source,
+                &mut rng,
+            );
+        }
+    }
+}
+
+fn tick_entry_delay(
+    time: Res<Time>,
+    mut rng: ResMut<SmallRng>,
+    mut piece_source: ResMut<PieceSource>,
+    mut entry_timer: ResMut<EntryTimer>,
+    mut commands: Commands,
+) {
+    if let Some(timer) = &mut entry_timer.0 {
+        if timer.tick(time.delta()).finished() {
+            entry_timer.0 = None;
+            if let Some(piece) = piece_source.next(&mut rng) {
+                spawn(&mut commands, piece);
             }
-            spawn(&mut commands, &mut rng);
         }
     }
 }
 
-fn lines_to_score(lines: u8) -> u32 {
-    match lines {
-        1 => 100,
-        2 => 300,
-        3 => 500,
-        4 => 800,
+/// Points awarded for each way of scoring
+struct ScoringConfig {
+    /// For clearing 1, 2, 3 or 4 lines at once
+    line_clears: [u32; 4],
+    /// Whether line clears are multiplied by the level plus one, as on the NES
+    per_level: bool,
+    /// For each row the piece falls while soft dropping
+    soft_drop_per_cell: u32,
+}
+
+/// Which era's point values to score with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ScoringSystem {
+    Guideline,
+    /// The NES values, which go up with the level
+    ClassicNes,
+}
+
+impl ScoringSystem {
+    /// Classic NES scoring if `TETRIS_SCORING` is `nes`
+    fn from_env() -> ScoringSystem {
+        match std::env::var("TETRIS_SCORING").as_deref() {
+            Ok("nes") => ScoringSystem::ClassicNes,
+            _ => ScoringSystem::Guideline,
+        }
+    }
+}
+
+impl ScoringConfig {
+    fn new(system: ScoringSystem) -> ScoringConfig {
+        let (line_clears, per_level) = match system {
+            ScoringSystem::Guideline => ([100, 300, 500, 800], false),
+            ScoringSystem::ClassicNes => ([40, 100, 300, 1200], true),
+        };
+        ScoringConfig {
+            line_clears,
+            per_level,
+            soft_drop_per_cell: 1,
+        }
+    }
+}
+
+/// The points for clearing `lines` at once while on `level`
+fn lines_to_score(lines: u8, level: u32, scoring: &ScoringConfig) -> u32 {
+    let points = match lines {
+        1..=4 => scoring.line_clears[usize::from(lines) - 1],
         _ => panic!("At most 4 lines can be cleared at once"),
+    };
+    if scoring.per_level {
+        points * (level + 1)
+    } else {
+        points
     }
 }
 
 fn clear_rows(
     mut score: ResMut<Score>,
+    scoring: Res<ScoringConfig>,
+    mut lines: ResMut<LinesCleared>,
     mut full_grid: ResMut<FullGrid>,
+    mut garbage_rows: ResMut<GarbageRows>,
     mut tiles: Query<(Entity, &mut Tile)>,
+    collapse_animation: Res<CollapseAnimation>,
+    reduce_motion: Res<ReduceMotion>,
+    gravity_mode: Res<GravityMode>,
     mut commands: Commands,
 ) {
     if !full_grid.is_changed() {
         return;
     }
-    let mut cleared = 0;
-    for y in (0..ROWS).rev() {
-        if full_grid.0[y] == [true; COLUMNS] {
-            full_grid.0[y..].rotate_left(1);
-            *full_grid.0.last_mut().unwrap() = [false; COLUMNS];
-            for (entity, mut tile) in &mut tiles {
-                match tile.y.cmp(&(y as i8)) {
-                    Ordering::Less => {}
-                    Ordering::Equal => commands.entity(entity).despawn(),
-                    Ordering::Greater => {
-                        tile.y -= 1;
+    let animate = collapse_animation.0 && !reduce_motion.0;
+    let mut despawned = Vec::new();
+    // Sticky gravity can fill more rows as the stack settles, which are cleared in turn
+    loop {
+        let mut cleared = 0;
+        for y in (0..ROWS).rev() {
+            if full_grid.0[y] == [true; COLUMNS] {
+                full_grid.0[y..].rotate_left(1);
+                *full_grid.0.last_mut().unwrap() = [false; COLUMNS];
+                for (entity, mut tile) in &mut tiles {
+                    match tile.y.cmp(&(y as i8)) {
+                        Ordering::Less => {}
+                        Ordering::Equal => {
+                            commands.entity(entity).despawn();
+                            despawned.push(entity);
+                        }
+                        Ordering::Greater => {
+                            tile.y -= 1;
+                            if animate && !despawned.contains(&entity) {
+                                commands
+                                    .entity(entity)
+                                    .insert(Collapse(Timer::new(COLLAPSE_DURATION, false)));
+                            }
+                        }
                     }
                 }
+                if y < garbage_rows.0 {
+                    garbage_rows.0 -= 1;
+                }
+                cleared += 1
+            }
+        }
+        if cleared == 0 {
+            break;
+        }
+        // A chain clear can fill more rows at once than a single piece can
+        score.0 += lines_to_score(cleared.min(4), lines.level(), &scoring);
+        lines.0 += u32::from(cleared);
+
+        if *gravity_mode != GravityMode::Sticky {
+            break;
+        }
+        let drops = settle(&mut full_grid);
+        if drops.is_empty() {
+            break;
+        }
+        for (entity, mut tile) in &mut tiles {
+            if despawned.contains(&entity) {
+                continue;
+            }
+            if let Some(&(_, distance)) = drops.iter().find(|(from, _)| *from == *tile) {
+                tile.y -= distance;
+                if animate {
+                    commands
+                        .entity(entity)
+                        .insert(Collapse(Timer::new(COLLAPSE_DURATION, false)));
+                }
             }
-            cleared += 1
         }
-    }
-    if cleared != 0 {
-        score.0 += lines_to_score(cleared)
     }
 }
 
-fn check_loss(full_grid: Res<FullGrid>, mut game_state: ResMut<State<GameState>>) {
+fn check_loss(
+    full_grid: Res<FullGrid>,
+    reduce_motion: Res<ReduceMotion>,
+    mut game_state: ResMut<State<GameState>>,
+) {
     if !full_grid.is_changed() {
         return;
     }
 
     if full_grid.0[ROWS..].iter().any(|row| *row != [false; 10]) {
+        end_game(&mut game_state, &reduce_motion);
+    }
+}
+
+/// Ends the game, covering the board with the curtain first unless motion is reduced.
+/// Something else may already have ended it this frame, in which case this does nothing.
+fn end_game(game_state: &mut State<GameState>, reduce_motion: &ReduceMotion) {
+    let next = if reduce_motion.0 {
+        GameState::GameOver
+    } else {
+        GameState::GameOverAnimation
+    };
+    let _ = game_state.set(next);
+}
+
+const CURTAIN_ROW_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Covers the board a row at a time before the game over screen
+struct Curtain {
+    timer: Timer,
+    rows: usize,
+}
+
+fn start_curtain(mut commands: Commands) {
+    commands.insert_resource(Curtain {
+        timer: Timer::new(CURTAIN_ROW_INTERVAL, true),
+        rows: 0,
+    });
+}
+
+fn fill_curtain(
+    time: Res<Time>,
+    mut curtain: ResMut<Curtain>,
+    mut game_state: ResMut<State<GameState>>,
+    mut commands: Commands,
+) {
+    for _ in 0..curtain.timer.tick(time.delta()).times_finished_this_tick() {
+        if curtain.rows == ROWS {
+            break;
+        }
+        let y = curtain.rows as i8;
+        for x in 0..COLUMNS as i8 {
+            let mut sprite = tile_sprite(CURTAIN);
+            sprite.transform.translation.z = Z_CURTAIN;
+            commands.spawn().insert_bundle(sprite).insert(Tile { x, y });
+        }
+        curtain.rows += 1;
+    }
+    if curtain.rows == ROWS {
         game_state.set(GameState::GameOver).unwrap();
     }
 }
 
-fn in_bounds(x: i8, y: i8) -> bool {
+fn in_bounds(x: i8, y: i8, hard_ceiling: bool) -> bool {
     // pieces are allowed to move above the screen,
     // you just lose if the piece is *placed* above the screen.
-    (0..COLUMNS as i8).contains(&x) && (0..).contains(&y)
+    // Under a hard ceiling they can't go above it at all.
+    (0..COLUMNS as i8).contains(&x) && (0..).contains(&y) && (!hard_ceiling || y < ROWS as i8)
 }
 
-fn update_segment(
-    tile: &mut Tile,
-    segment: &mut FallingSegment,
-    left: bool,
-    right: bool,
-    z: bool,
-    x: bool,
+fn quit(keyboard_input: Res<Input<KeyCode>>, mut exit: EventWriter<AppExit>) {
+    let ctrl = keyboard_input.any_pressed([KeyCode::LControl, KeyCode::RControl]);
+    if ctrl && keyboard_input.just_pressed(KeyCode::Q) {
+        exit.send(AppExit);
+    }
+}
+
+fn toggle_settings(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut collapse_animation: ResMut<CollapseAnimation>,
+    mut reduce_motion: ResMut<ReduceMotion>,
+    mut column_stats_overlay: ResMut<ColumnStatsOverlay>,
+    mut ai_controlled: ResMut<AiControlled>,
+    mut latency_test: ResMut<LatencyTest>,
+    mut danger_line_visible: ResMut<DangerLineVisible>,
+    mut layout: ResMut<BoardLayout>,
+    mut mirror_controls: ResMut<MirrorControls>,
+    mut practice_mode: ResMut<PracticeMode>,
 ) {
-    let mut focal_point_x = tile.x - segment.x_offset;
-    let focal_point_y = tile.y - segment.y_offset;
-    if left {
-        focal_point_x -= 1;
+    if keyboard_input.just_pressed(KeyCode::F1) {
+        collapse_animation.0 = !collapse_animation.0;
+    }
+    if keyboard_input.just_pressed(KeyCode::F10) {
+        reduce_motion.0 = !reduce_motion.0;
+    }
+    if keyboard_input.just_pressed(KeyCode::F4) {
+        column_stats_overlay.0 = !column_stats_overlay.0;
+    }
+    if keyboard_input.just_pressed(KeyCode::F5) {
+        ai_controlled.0 = !ai_controlled.0;
+    }
+    if keyboard_input.just_pressed(KeyCode::F6) {
+        latency_test.0 = !latency_test.0;
     }
-    if right {
-        focal_point_x += 1;
+    if keyboard_input.just_pressed(KeyCode::F7) {
+        danger_line_visible.0 = !danger_line_visible.0;
     }
-    if z {
-        *segment = segment.rotate_counterclockwise();
+    if keyboard_input.just_pressed(KeyCode::F8) {
+        layout.size = layout.size.next();
     }
-    if x {
-        *segment = segment.rotate_clockwise();
+    if keyboard_input.just_pressed(KeyCode::F11) {
+        mirror_controls.0 = !mirror_controls.0;
     }
-    *tile = Tile {
-        x: focal_point_x + segment.x_offset,
-        y: focal_point_y + segment.y_offset,
+    if keyboard_input.just_pressed(KeyCode::F12) {
+        practice_mode.0 = !practice_mode.0;
     }
 }
 
+/// Alt and the arrow keys pan the camera instead of moving the piece
+fn alt_held(keyboard_input: &Input<KeyCode>) -> bool {
+    keyboard_input.any_pressed([KeyCode::LAlt, KeyCode::RAlt])
+}
+
 fn handle_input(
     keyboard_input: Res<Input<KeyCode>>,
     mut query: Query<(&mut Tile, &mut FallingSegment)>,
     full_grid: Res<FullGrid>,
     mut fall_timer: ResMut<FallTimer>,
+    natural_fall: Res<NaturalFall>,
+    mut buffered: ResMut<BufferedInput>,
+    time: Res<Time>,
+    latency_test: Res<LatencyTest>,
+    mut latency_probe: ResMut<LatencyProbe>,
+    mirror_controls: Res<MirrorControls>,
+    wrap_mode: Res<WrapMode>,
+    hard_ceiling: Res<HardCeiling>,
 ) {
-    let left = keyboard_input.just_pressed(KeyCode::Left);
-    let right = keyboard_input.just_pressed(KeyCode::Right);
-    let z = keyboard_input.just_pressed(KeyCode::Z);
-    let x = keyboard_input.just_pressed(KeyCode::X);
-    if keyboard_input.just_pressed(KeyCode::Down) {
-        let new_duration = fall_timer.0.duration() / 3;
-        fall_timer.0.set_duration(new_duration);
+    let arrows = !alt_held(&keyboard_input);
+    let mut left = arrows && keyboard_input.just_pressed(KeyCode::Left);
+    let mut right = arrows && keyboard_input.just_pressed(KeyCode::Right);
+    let mut z = keyboard_input.just_pressed(KeyCode::Z);
+    let mut x = keyboard_input.just_pressed(KeyCode::X);
+    let mut a = keyboard_input.just_pressed(KeyCode::A);
+    let sonic_drop = arrows && keyboard_input.just_pressed(KeyCode::Up);
+    if mirror_controls.0 {
+        std::mem::swap(&mut left, &mut right);
+        std::mem::swap(&mut z, &mut x);
+    }
+    // Set from the unsped fall rate rather than scaled back up on release, so it
+    // can't drift if gravity changes or a game starts while Down is held
+    if arrows && keyboard_input.just_pressed(KeyCode::Down) {
+        fall_timer.0.set_duration(natural_fall.timer.duration() / 3);
     }
     if keyboard_input.just_released(KeyCode::Down) {
-        let new_duration = fall_timer.0.duration() * 3;
-        fall_timer.0.set_duration(new_duration);
+        fall_timer.0.set_duration(natural_fall.timer.duration());
+    }
+    if query.is_empty() {
+        // No piece to move during the entry delay, so hold on to the input
+        buffered.left |= left;
+        buffered.right |= right;
+        buffered.z |= z;
+        buffered.x |= x;
+        buffered.a |= a;
+        return;
+    }
+    let pressed = left || right || z || x || a || sonic_drop;
+    left |= std::mem::take(&mut buffered.left);
+    right |= std::mem::take(&mut buffered.right);
+    z |= std::mem::take(&mut buffered.z);
+    x |= std::mem::take(&mut buffered.x);
+    a |= std::mem::take(&mut buffered.a);
+    if !left && !right && !z && !x && !a && !sonic_drop {
+        return;
+    }
+    let mut piece: Vec<(Tile, FallingSegment)> = query
+        .iter()
+        .map(|(tile, segment)| (*tile, *segment))
+        .collect();
+    let mut moved = false;
+    let dx = i8::from(right) - i8::from(left);
+    if dx != 0 {
+        if let Some(new_piece) = try_move(&piece, dx, wrap_mode.0, hard_ceiling.0, &full_grid) {
+            piece = new_piece;
+            moved = true;
+        }
+    }
+    let rotations: [(bool, fn(FallingSegment) -> FallingSegment); 3] = [
+        (z, FallingSegment::rotate_counterclockwise),
+        (x, FallingSegment::rotate_clockwise),
+        (a, |segment| segment.rotate_clockwise().rotate_clockwise()),
+    ];
+    for (_, rotation) in rotations.into_iter().filter(|(pressed, _)| *pressed) {
+        if let Some(new_piece) =
+            try_rotate(&piece, rotation, wrap_mode.0, hard_ceiling.0, &full_grid)
+        {
+            piece = new_piece;
+            moved = true;
+        }
+    }
+    if sonic_drop {
+        // Drops to the floor without locking, so the piece can still slide
+        let tiles: Vec<Tile> = piece.iter().map(|&(tile, _)| tile).collect();
+        let distance = compute_drop_distance(&tiles, hard_ceiling.0, &full_grid);
+        if distance != 0 {
+            for (tile, _) in &mut piece {
+                tile.y -= distance;
+            }
+            moved = true;
+        }
     }
-    if !left && !right && !z && !x {
+    if !moved {
         return;
     }
-    let new_segments = query.iter().map(|(tile, segment)| {
-        let mut new_tile = *tile;
-        let mut new_segment = *segment;
-        update_segment(&mut new_tile, &mut new_segment, left, right, z, x);
-        new_tile
+    if latency_test.0 && pressed {
+        latency_probe.pressed = time.last_update();
+    }
+    for ((mut tile, mut segment), (new_tile, new_segment)) in query.iter_mut().zip(piece) {
+        if new_tile != *tile {
+            *tile = new_tile;
+        }
+        if new_segment != *segment {
+            *segment = new_segment;
+        }
+    }
+}
+
+fn reset_piece_to_spawn(
+    keyboard_input: Res<Input<KeyCode>>,
+    practice_mode: Res<PracticeMode>,
+    reset_key: Res<ResetPieceKey>,
+    full_grid: Res<FullGrid>,
+    hard_ceiling: Res<HardCeiling>,
+    mut segments: Query<(&mut Tile, &mut FallingSegment, &SpawnState)>,
+) {
+    if !practice_mode.0 || !keyboard_input.just_pressed(reset_key.0) {
+        return;
+    }
+    let spawn_tiles = segments.iter().map(|(_, _, spawn)| spawn.tile);
+    if !fits_at(spawn_tiles, hard_ceiling.0, &full_grid) {
+        return;
+    }
+    for (mut tile, mut segment, spawn) in &mut segments {
+        *tile = spawn.tile;
+        *segment = spawn.segment;
+    }
+}
+
+/// Sends a locked piece's sprites behind whatever piece falls next
+fn move_locked_tiles_to_stack(
+    locked: RemovedComponents<FallingSegment>,
+    mut transforms: Query<&mut Transform>,
+) {
+    for entity in locked.iter() {
+        if let Ok(mut transform) = transforms.get_mut(entity) {
+            transform.translation.z = Z_STACK;
+        }
+    }
+}
+
+fn animate_spawn_flash(
+    time: Res<Time>,
+    layout: Res<BoardLayout>,
+    reduce_motion: Res<ReduceMotion>,
+    mut flashes: Query<(Entity, &mut Transform, &mut Sprite, &mut SpawnFlash)>,
+    mut commands: Commands,
+) {
+    for (entity, mut transform, mut sprite, mut flash) in &mut flashes {
+        if reduce_motion.0 || flash.timer.tick(time.delta()).finished() {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+        let position = layout.cell_position(f32::from(flash.cell.x), f32::from(flash.cell.y));
+        transform.translation = position.extend(Z_SPAWN_FLASH);
+        transform.scale = Vec3::new(layout.cell_size(), layout.cell_size(), 1.0);
+        sprite
+            .color
+            .set_a(SPAWN_FLASH.a() * (1.0 - flash.timer.percent()));
+    }
+}
+
+fn animate_spawn_fade(
+    time: Res<Time>,
+    reduce_motion: Res<ReduceMotion>,
+    opacity: Res<TileOpacity>,
+    mut tiles: Query<(Entity, &mut Sprite, &mut SpawnFade)>,
+    mut commands: Commands,
+) {
+    for (entity, mut sprite, mut fade) in &mut tiles {
+        if reduce_motion.0 || fade.0.tick(time.delta()).finished() {
+            sprite.color.set_a(opacity.0);
+            commands.entity(entity).remove::<SpawnFade>();
+        } else {
+            sprite.color.set_a(fade.0.percent() * opacity.0);
+        }
+    }
+}
+
+fn animate_lock_flash(
+    time: Res<Time>,
+    reduce_motion: Res<ReduceMotion>,
+    mut tiles: Query<(Entity, &mut Sprite, &mut LockFlash, Option<&BaseColor>)>,
+    mut commands: Commands,
+) {
+    for (entity, mut sprite, mut flash, base) in &mut tiles {
+        let color = *flash
+            .color
+            .get_or_insert(base.map_or(sprite.color, |base| base.0));
+        let alpha = sprite.color.a();
+        if reduce_motion.0 || flash.timer.tick(time.delta()).finished() {
+            sprite.color = color;
+            sprite.color.set_a(alpha);
+            commands.entity(entity).remove::<LockFlash>();
+        } else {
+            let [r, g, b, _] = color.as_rgba_f32();
+            let t = flash.timer.percent();
+            sprite.color = Color::rgba(
+                1.0 + (r - 1.0) * t,
+                1.0 + (g - 1.0) * t,
+                1.0 + (b - 1.0) * t,
+                alpha,
+            );
+        }
+    }
+}
+
+fn animate_collapse(
+    time: Res<Time>,
+    layout: Res<BoardLayout>,
+    collapse_animation: Res<CollapseAnimation>,
+    reduce_motion: Res<ReduceMotion>,
+    mut tiles: Query<(Entity, &mut Transform, &Tile, &mut Collapse)>,
+    mut commands: Commands,
+) {
+    for (entity, mut transform, tile, mut collapse) in &mut tiles {
+        let target = layout.cell_position(tile.x as f32, tile.y as f32);
+        transform.translation.x = target.x;
+        let remaining = collapse.0.duration().saturating_sub(collapse.0.elapsed());
+        if !collapse_animation.0 || reduce_motion.0 || collapse.0.tick(time.delta()).finished() {
+            transform.translation.y = target.y;
+            commands.entity(entity).remove::<Collapse>();
+        } else {
+            // Cover the rest of the distance evenly over the remaining time
+            let progress = (time.delta_seconds() / remaining.as_secs_f32()).min(1.0);
+            transform.translation.y += (target.y - transform.translation.y) * progress;
+        }
+    }
+}
+
+fn update_translation(
+    layout: Res<BoardLayout>,
+    mut latency_probe: ResMut<LatencyProbe>,
+    mut tiles: Query<(&mut Transform, &Tile, ChangeTrackers<Tile>), Without<Collapse>>,
+) {
+    let layout_changed = layout.is_changed();
+    tiles.par_for_each_mut(COLUMNS, |(mut transform, tile, tracker)| {
+        if layout_changed || tracker.is_changed() {
+            let position = layout.cell_position(tile.x as f32, tile.y as f32);
+            transform.translation.x = position.x;
+            transform.translation.y = position.y;
+            transform.scale = Vec3::new(layout.cell_size(), layout.cell_size(), 1.0);
+        }
     });
-    if can_fit(new_segments, &full_grid) {
-        for (mut tile, mut segment) in &mut query {
-            let mut new_tile = *tile;
-            let mut new_segment = *segment;
-            update_segment(&mut new_tile, &mut new_segment, left, right, z, x);
-            if new_tile != *tile {
-                *tile = new_tile;
+    if latency_probe.pressed.is_some() && tiles.iter().any(|(_, _, tracker)| tracker.is_changed()) {
+        latency_probe.drawn = latency_probe.pressed.take();
+    }
+}
+
+fn grid_background_transform(layout: &BoardLayout) -> Transform {
+    let position = layout.cell_position((COLUMNS - 1) as f32 / 2.0, (ROWS - 1) as f32 / 2.0);
+    Transform {
+        translation: position.extend(Z_GRID),
+        scale: Vec3::new(
+            COLUMNS as f32 * layout.cell_size(),
+            ROWS as f32 * layout.cell_size(),
+            1.0,
+        ),
+        ..Default::default()
+    }
+}
+
+fn danger_line_transform(layout: &BoardLayout, row: usize) -> Transform {
+    let position = layout.cell_position((COLUMNS - 1) as f32 / 2.0, row as f32 - 0.5);
+    Transform {
+        translation: position.extend(Z_DANGER_LINE),
+        scale: Vec3::new(COLUMNS as f32 * layout.cell_size(), 2.0, 1.0),
+        ..Default::default()
+    }
+}
+
+fn update_danger_line(
+    layout: Res<BoardLayout>,
+    row: Res<DangerLineRow>,
+    visible: Res<DangerLineVisible>,
+    mut line: Query<(&mut Transform, &mut Visibility), With<DangerLine>>,
+) {
+    if !layout.is_changed() && !row.is_changed() && !visible.is_changed() {
+        return;
+    }
+    for (mut transform, mut visibility) in &mut line {
+        *transform = danger_line_transform(&layout, row.0);
+        visibility.is_visible = visible.0;
+    }
+}
+
+fn buffer_mask_transform(layout: &BoardLayout) -> Transform {
+    let position = layout.cell_position(
+        (COLUMNS - 1) as f32 / 2.0,
+        ROWS as f32 + (BUFFER_ROWS - 1) as f32 / 2.0,
+    );
+    Transform {
+        translation: position.extend(Z_BUFFER_MASK),
+        scale: Vec3::new(
+            COLUMNS as f32 * layout.cell_size(),
+            BUFFER_ROWS as f32 * layout.cell_size(),
+            1.0,
+        ),
+        ..Default::default()
+    }
+}
+
+/// Keeps the mask over the buffer rows and matching the window's background color.
+/// Over a background image it shows as a band of the fallback color.
+fn update_buffer_mask(
+    layout: Res<BoardLayout>,
+    clear_color: Res<ClearColor>,
+    mut mask: Query<(&mut Transform, &mut Sprite), With<BufferMask>>,
+) {
+    if !layout.is_changed() && !clear_color.is_changed() {
+        return;
+    }
+    for (mut transform, mut sprite) in &mut mask {
+        *transform = buffer_mask_transform(&layout);
+        sprite.color = clear_color.0;
+    }
+}
+
+fn update_grid_background(
+    layout: Res<BoardLayout>,
+    mut background: Query<&mut Transform, With<GridBackground>>,
+) {
+    if !layout.is_changed() {
+        return;
+    }
+    for mut transform in &mut background {
+        *transform = grid_background_transform(&layout);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::schedule::{Stage, SystemStage};
+
+    use super::*;
+    use crate::testing::*;
+    use crate::tetrominoes::PieceKind;
+
+    #[test]
+    fn alt_down_doesnt_speed_up_the_fall() {
+        let mut world = game();
+        press(&mut world, &[KeyCode::LAlt, KeyCode::Down]);
+        run(&mut world, handle_input);
+        assert_eq!(world.resource::<FallTimer>().0.duration(), FALL_INTERVAL);
+    }
+
+    #[test]
+    fn alt_down_doesnt_score_or_lock() {
+        let mut world = game();
+        world.insert_resource(SoftDropLocks(true));
+        spawn_piece(&mut world, PieceKind::O);
+        ground_piece(&mut world);
+        let grounded = piece_cells(&mut world);
+        press(&mut world, &[KeyCode::LAlt, KeyCode::Down]);
+        run(&mut world, fall);
+        assert_eq!(piece_cells(&mut world), grounded);
+
+        // Panning while the piece falls on its own doesn't count as soft drop
+        shift_piece(&mut world, 0, 5);
+        world
+            .resource_mut::<FallTimer>()
+            .0
+            .set_duration(FALL_INTERVAL / 3);
+        for _ in 0..3 {
+            next_frame(&mut world);
+            advance(&mut world, FALL_INTERVAL / 3);
+            run(&mut world, fall);
+        }
+        assert_eq!(world.resource::<Score>().0, 0);
+    }
+
+    #[test]
+    fn sticky_gravity_chains_clears() {
+        let mut world = game();
+        world.insert_resource(GravityMode::Sticky);
+        // Clearing the second row drops the lone block into the gap below it
+        let mut cells: Vec<_> = (1..COLUMNS as i8).map(|x| (x, 0)).collect();
+        cells.extend((0..COLUMNS as i8).map(|x| (x, 1)));
+        cells.push((0, 2));
+        fill(&mut world, &cells);
+        run(&mut world, clear_rows);
+
+        assert_eq!(world.resource::<LinesCleared>().0, 2);
+        assert_eq!(world.resource::<Score>().0, 200);
+        assert!(world
+            .resource::<FullGrid>()
+            .0
+            .iter()
+            .all(|row| !row.contains(&true)));
+        assert_eq!(world.query::<&Tile>().iter(&world).count(), 0);
+    }
+
+    #[test]
+    fn no_piece_falls_during_the_entry_delay() {
+        let mut world = game();
+        world.insert_resource(EntryDelay(Duration::from_millis(500)));
+        spawn_piece(&mut world, PieceKind::O);
+        ground_piece(&mut world);
+        advance(&mut world, FALL_INTERVAL);
+        run(&mut world, fall);
+        assert!(piece_cells(&mut world).is_empty());
+
+        // Nothing spawns until the delay has run out
+        for _ in 0..4 {
+            advance(&mut world, Duration::from_millis(100));
+            run(&mut world, tick_entry_delay);
+            assert!(piece_cells(&mut world).is_empty());
+        }
+        advance(&mut world, Duration::from_millis(100));
+        run(&mut world, tick_entry_delay);
+        assert_eq!(piece_cells(&mut world).len(), 4);
+    }
+
+    #[test]
+    fn soft_drop_locks_a_grounded_piece_when_enabled() {
+        let mut world = game();
+        world.insert_resource(SoftDropLocks(true));
+        spawn_piece(&mut world, PieceKind::O);
+        ground_piece(&mut world);
+        press(&mut world, &[KeyCode::Down]);
+        run(&mut world, fall);
+        assert_eq!(world.resource::<PiecesPlaced>().0, 1);
+        assert!(world.resource::<FullGrid>().0[0].contains(&true));
+    }
+
+    #[test]
+    fn soft_drop_waits_for_gravity_when_disabled() {
+        let mut world = game();
+        spawn_piece(&mut world, PieceKind::O);
+        ground_piece(&mut world);
+        let grounded = piece_cells(&mut world);
+        press(&mut world, &[KeyCode::Down]);
+        run(&mut world, fall);
+        assert_eq!(piece_cells(&mut world), grounded);
+        assert_eq!(world.resource::<PiecesPlaced>().0, 0);
+    }
+
+    #[test]
+    fn spawn_flash_shows_inside_the_board() {
+        for kind in [PieceKind::I, PieceKind::T, PieceKind::O] {
+            let mut world = game();
+            spawn_piece(&mut world, kind);
+            let piece = piece_cells(&mut world);
+            let height = piece.iter().map(|&(_, y)| y).max().unwrap() - ROWS as i8 + 1;
+            let mut flashes: Vec<_> = world
+                .query::<&SpawnFlash>()
+                .iter(&world)
+                .map(|flash| (flash.cell.x, flash.cell.y))
+                .collect();
+            flashes.sort_unstable();
+            // The piece's footprint, moved down into the top rows
+            let expected: Vec<_> = piece.iter().map(|&(x, y)| (x, y - height)).collect();
+            assert_eq!(flashes, expected);
+            assert!(flashes.iter().all(|&(_, y)| y < ROWS as i8));
+        }
+    }
+
+    /// Plays a game of `mode` from `seed` with the same inputs on the same frames,
+    /// returning the final board, the score and the colors of the pieces dealt
+    fn replay(
+        seed: u64,
+        mode: GameMode,
+    ) -> ([[bool; COLUMNS]; ROWS + BUFFER_ROWS], u32, Vec<Color>) {
+        let mut world = game();
+        world.insert_resource(mode);
+        world.insert_resource(PieceSequenceFile(None));
+        world.insert_resource(PieceSet::Tetrominoes);
+        world.insert_resource(RngSeed(Some(seed)));
+        world.insert_resource(State::new(GameState::Playing));
+        world.insert_resource(AiControlled(false));
+        world.insert_resource(PracticeMode(false));
+        world.insert_resource(ResetPieceKey(KeyCode::R));
+        world.insert_resource(MirrorOnClear(false));
+        run(&mut world, start_game);
+        let mut stage = SystemStage::single_threaded();
+        stage.add_system_set(board_systems(SystemSet::new()));
+        let mut colors = Vec::new();
+        let mut falling = None;
+        for frame in 0..1500 {
+            next_frame(&mut world);
+            let mut input = world.resource_mut::<Input<KeyCode>>();
+            match frame % 45 {
+                0 => input.press(KeyCode::Left),
+                10 => input.press(KeyCode::X),
+                20 => input.press(KeyCode::Right),
+                25 => input.press(KeyCode::Right),
+                30 => input.press(KeyCode::Down),
+                40 => input.release(KeyCode::Down),
+                _ => {}
             }
-            if new_segment != *segment {
-                *segment = new_segment;
+            for key in [KeyCode::Left, KeyCode::Right, KeyCode::X] {
+                if !input.just_pressed(key) {
+                    input.release(key);
+                }
+            }
+            advance(&mut world, FIXED_STEP);
+            stage.run(&mut world);
+
+            let piece = world
+                .query_filtered::<(Entity, &Sprite), With<FallingSegment>>()
+                .iter(&world)
+                .next()
+                .map(|(entity, sprite)| (entity, sprite.color));
+            if let Some((entity, color)) = piece {
+                if falling != Some(entity) {
+                    falling = Some(entity);
+                    colors.push(color);
+                }
             }
         }
+        let board = world.resource::<FullGrid>().0;
+        (board, world.resource::<Score>().0, colors)
     }
-}
 
-fn update_translation(mut tiles: Query<(&mut Transform, &Tile), Changed<Tile>>) {
-    tiles.par_for_each_mut(COLUMNS, |(mut transform, tile)| {
-        transform.translation.x = GRID_START_X + (tile.x as f32 * CELL_SIZE as f32);
-        transform.translation.y = GRID_START_Y + (tile.y as f32 * CELL_SIZE as f32);
-    })
+    #[test]
+    fn replays_play_out_the_same() {
+        let (board, score, pieces) = replay(7, GameMode::Marathon);
+        assert!(pieces.len() > 5);
+        assert_eq!(replay(7, GameMode::Marathon), (board, score, pieces));
+    }
+
+    #[test]
+    fn replays_with_garbage_play_out_the_same() {
+        for mode in [GameMode::Cheese { rows: 6 }, GameMode::Versus] {
+            let (board, score, pieces) = replay(7, mode.clone());
+            assert!(pieces.len() > 5);
+            assert_eq!(replay(7, mode), (board, score, pieces));
+        }
+    }
+
+    #[test]
+    fn cheese_starts_with_its_garbage_rows() {
+        let mut world = game();
+        world.insert_resource(GameMode::Cheese { rows: 9 });
+        world.insert_resource(PieceSequenceFile(None));
+        world.insert_resource(PieceSet::Tetrominoes);
+        world.insert_resource(RngSeed(None));
+        run(&mut world, start_game);
+
+        let full_grid = world.resource::<FullGrid>();
+        for row in &full_grid.0[..9] {
+            assert_eq!(row.iter().filter(|&&cell| !cell).count(), 1);
+        }
+        assert!(full_grid.0[9..].iter().all(|row| !row.contains(&true)));
+        assert_eq!(world.resource::<GarbageRows>().0, 9);
+        let locked = world
+            .query_filtered::<&Tile, Without<FallingSegment>>()
+            .iter(&world)
+            .count();
+        assert_eq!(locked, 9 * (COLUMNS - 1));
+    }
+
+    /// Fills every cell of the given rows
+    fn full_rows(rows: std::ops::Range<i8>) -> Vec<(i8, i8)> {
+        rows.flat_map(|y| (0..COLUMNS as i8).map(move |x| (x, y)))
+            .collect()
+    }
+
+    #[test]
+    fn custom_scoring_values_add_up() {
+        let mut world = game();
+        world.insert_resource(ScoringConfig {
+            line_clears: [1, 20, 300, 4000],
+            per_level: false,
+            soft_drop_per_cell: 7,
+        });
+        fill(&mut world, &full_rows(0..2));
+        run(&mut world, clear_rows);
+        assert_eq!(world.resource::<Score>().0, 20);
+
+        // Two rows of soft drop, before gravity would have moved the piece
+        spawn_piece(&mut world, PieceKind::O);
+        press(&mut world, &[KeyCode::Down]);
+        run(&mut world, handle_input);
+        for _ in 0..2 {
+            advance(&mut world, FALL_INTERVAL / 3);
+            run(&mut world, fall);
+        }
+        assert_eq!(world.resource::<Score>().0, 20 + 2 * 7);
+
+        fill(&mut world, &full_rows(0..4));
+        run(&mut world, clear_rows);
+        assert_eq!(world.resource::<Score>().0, 20 + 2 * 7 + 4000);
+        assert_eq!(world.resource::<LinesCleared>().0, 6);
+    }
+
+    /// A piece with its focal point at `(x, y)`
+    fn placed(kind: PieceKind, x: i8, y: i8) -> Vec<(Tile, FallingSegment)> {
+        kind.tetromino()
+            .shape
+            .iter()
+            .map(|&segment| {
+                let tile = Tile {
+                    x: x + segment.x_offset,
+                    y: y + segment.y_offset,
+                };
+                (tile, segment)
+            })
+            .collect()
+    }
+
+    /// The piece's cells, sorted
+    fn cells(piece: &[(Tile, FallingSegment)]) -> Vec<(i8, i8)> {
+        let mut cells: Vec<_> = piece.iter().map(|(tile, _)| (tile.x, tile.y)).collect();
+        cells.sort_unstable();
+        cells
+    }
+
+    #[test]
+    fn fits_inside_the_board_and_above_it() {
+        let mut full_grid = FullGrid::empty();
+        full_grid.0[0][3] = true;
+        assert!(fits_at(
+            [Tile { x: 0, y: 0 }, Tile { x: 9, y: 19 }],
+            false,
+            &full_grid
+        ));
+        assert!(fits_at(
+            [Tile {
+                x: 4,
+                y: ROWS as i8 + 2
+            }],
+            false,
+            &full_grid
+        ));
+        assert!(!fits_at([Tile { x: 3, y: 0 }], false, &full_grid));
+        assert!(!fits_at([Tile { x: -1, y: 5 }], false, &full_grid));
+        assert!(!fits_at([Tile { x: 10, y: 5 }], false, &full_grid));
+        assert!(!fits_at([Tile { x: 5, y: -1 }], false, &full_grid));
+    }
+
+    #[test]
+    fn hard_ceiling_keeps_tiles_on_the_board() {
+        let full_grid = FullGrid::empty();
+        let top = ROWS as i8 - 1;
+        assert!(fits_at([Tile { x: 4, y: top }], true, &full_grid));
+        assert!(!fits_at([Tile { x: 4, y: top + 1 }], true, &full_grid));
+        let piece = placed(PieceKind::T, 4, top - 1);
+        assert!(try_move(&piece, 1, false, true, &full_grid).is_some());
+        let piece = placed(PieceKind::T, 4, top);
+        assert!(try_move(&piece, 1, false, true, &full_grid).is_none());
+    }
+
+    #[test]
+    fn moves_until_blocked() {
+        let mut full_grid = FullGrid::empty();
+        let piece = placed(PieceKind::T, 4, 0);
+        let moved = try_move(&piece, 1, false, false, &full_grid).unwrap();
+        assert_eq!(cells(&moved), [(4, 0), (5, 0), (5, 1), (6, 0)]);
+
+        // Against the wall
+        let piece = placed(PieceKind::T, 8, 0);
+        assert!(try_move(&piece, 1, false, false, &full_grid).is_none());
+        assert!(try_move(&piece, -1, false, false, &full_grid).is_some());
+
+        // Against the stack
+        full_grid.0[0][6] = true;
+        let piece = placed(PieceKind::T, 4, 0);
+        assert!(try_move(&piece, 1, false, false, &full_grid).is_none());
+    }
+
+    #[test]
+    fn rotates_in_place_unless_blocked() {
+        let mut full_grid = FullGrid::empty();
+        let piece = placed(PieceKind::T, 4, 5);
+        let turned = try_rotate(
+            &piece,
+            FallingSegment::rotate_clockwise,
+            false,
+            false,
+            &full_grid,
+        )
+        .unwrap();
+        assert_eq!(cells(&turned), [(4, 4), (4, 5), (4, 6), (5, 5)]);
+
+        // The cell below the focal point is taken, and there's no kick
+        full_grid.0[4][4] = true;
+        let rotation = FallingSegment::rotate_clockwise;
+        assert!(try_rotate(&piece, rotation, false, false, &full_grid).is_none());
+    }
+
+    #[test]
+    fn hard_ceiling_blocks_rotating_above_the_board() {
+        let full_grid = FullGrid::empty();
+        // Pointing down with its flat side along the top row, so turning
+        // pokes a cell up through the ceiling
+        let piece = placed(PieceKind::T, 4, ROWS as i8 - 1);
+        let flip = |segment: FallingSegment| segment.rotate_clockwise().rotate_clockwise();
+        let piece = try_rotate(&piece, flip, false, false, &full_grid).unwrap();
+        assert!(cells(&piece).iter().all(|&(_, y)| y < ROWS as i8));
+
+        let rotation = FallingSegment::rotate_clockwise;
+        assert!(try_rotate(&piece, rotation, false, false, &full_grid).is_some());
+        assert!(try_rotate(&piece, rotation, false, true, &full_grid).is_none());
+    }
+
+    #[test]
+    fn t_turns_180_beside_the_wall() {
+        let mut world = game();
+        spawn_piece(&mut world, PieceKind::T);
+        shift_piece(&mut world, -5, -10);
+        assert_eq!(
+            piece_cells(&mut world),
+            [(0, 10), (1, 10), (1, 11), (2, 10)]
+        );
+        press(&mut world, &[KeyCode::A]);
+        run(&mut world, handle_input);
+        assert_eq!(piece_cells(&mut world), [(0, 10), (1, 9), (1, 10), (2, 10)]);
+    }
+
+    #[test]
+    fn t_stays_put_when_turning_180_would_go_through_the_wall() {
+        let mut world = game();
+        spawn_piece(&mut world, PieceKind::T);
+        press(&mut world, &[KeyCode::X]);
+        run(&mut world, handle_input);
+        next_frame(&mut world);
+        // Pointing right, with its flat side along the wall
+        shift_piece(&mut world, -6, -10);
+        let against_wall = piece_cells(&mut world);
+        assert_eq!(against_wall, [(0, 9), (0, 10), (0, 11), (1, 10)]);
+        press(&mut world, &[KeyCode::A]);
+        run(&mut world, handle_input);
+        assert_eq!(piece_cells(&mut world), against_wall);
+    }
+
+    #[test]
+    fn releasing_down_restores_the_current_gravity() {
+        let mut world = game();
+        world.insert_resource(GameMode::Blitz);
+        world.insert_resource(State::new(GameState::Playing));
+        press(&mut world, &[KeyCode::Down]);
+        run(&mut world, handle_input);
+        assert_eq!(
+            world.resource::<FallTimer>().0.duration(),
+            FALL_INTERVAL / 3
+        );
+
+        // Blitz speeds gravity up while Down is held
+        world.insert_resource(PlayTime(Duration::from_secs(30)));
+        run(&mut world, run_blitz);
+        next_frame(&mut world);
+        world
+            .resource_mut::<Input<KeyCode>>()
+            .release(KeyCode::Down);
+        run(&mut world, handle_input);
+        let natural = world.resource::<NaturalFall>().timer.duration();
+        assert_eq!(natural, FALL_INTERVAL.mul_f32(0.75));
+        assert_eq!(world.resource::<FallTimer>().0.duration(), natural);
+    }
+
+    #[test]
+    fn a_long_frame_drops_the_piece_one_row() {
+        let mut world = game();
+        spawn_piece(&mut world, PieceKind::T);
+        shift_piece(&mut world, 0, -10);
+        let before = piece_cells(&mut world);
+        advance(&mut world, Duration::from_secs(2));
+        run(&mut world, fall);
+        let after: Vec<_> = before.iter().map(|&(x, y)| (x, y - 1)).collect();
+        assert_eq!(piece_cells(&mut world), after);
+
+        // The missed steps aren't made up afterwards
+        advance(&mut world, FALL_INTERVAL / 2);
+        run(&mut world, fall);
+        assert_eq!(piece_cells(&mut world), after);
+    }
+
+    /// The score for letting a T fall six rows, with or without soft drop
+    fn score_for_six_rows(soft_drop: bool) -> u32 {
+        let mut world = game();
+        spawn_piece(&mut world, PieceKind::T);
+        shift_piece(&mut world, 0, -10);
+        let start = piece_cells(&mut world);
+        let frame = if soft_drop {
+            press(&mut world, &[KeyCode::Down]);
+            run(&mut world, handle_input);
+            // Rounded up, so that three frames make up a whole fall interval
+            FALL_INTERVAL / 3 + Duration::from_nanos(1)
+        } else {
+            FALL_INTERVAL
+        };
+        for _ in 0..6 {
+            next_frame(&mut world);
+            advance(&mut world, frame);
+            run(&mut world, fall);
+        }
+        let landed: Vec<_> = start.iter().map(|&(x, y)| (x, y - 6)).collect();
+        assert_eq!(piece_cells(&mut world), landed);
+        world.resource::<Score>().0
+    }
+
+    #[test]
+    fn soft_drop_scores_only_the_rows_beyond_gravity() {
+        assert_eq!(score_for_six_rows(false), 0);
+        // Gravity would have moved the piece two of the six rows in that time
+        assert_eq!(score_for_six_rows(true), 4);
+    }
+
+    #[test]
+    fn mirrored_controls_swap_left_and_right() {
+        let mut world = game();
+        world.insert_resource(MirrorControls(true));
+        spawn_piece(&mut world, PieceKind::T);
+        let spawned = piece_cells(&mut world);
+        press(&mut world, &[KeyCode::Left]);
+        run(&mut world, handle_input);
+        let moved: Vec<_> = spawned.iter().map(|&(x, y)| (x + 1, y)).collect();
+        assert_eq!(piece_cells(&mut world), moved);
+
+        // Z turns the piece the way X usually does
+        let mut unmirrored = game();
+        spawn_piece(&mut unmirrored, PieceKind::T);
+        shift_piece(&mut unmirrored, 1, 0);
+        press(&mut unmirrored, &[KeyCode::X]);
+        run(&mut unmirrored, handle_input);
+        next_frame(&mut world);
+        press(&mut world, &[KeyCode::Z]);
+        run(&mut world, handle_input);
+        assert_eq!(piece_cells(&mut world), piece_cells(&mut unmirrored));
+    }
+
+    #[test]
+    fn practice_reset_puts_the_piece_back_at_its_spawn() {
+        let mut world = game();
+        world.insert_resource(PracticeMode(true));
+        world.insert_resource(ResetPieceKey(KeyCode::R));
+        spawn_piece(&mut world, PieceKind::T);
+        let spawned = piece_cells(&mut world);
+        press(&mut world, &[KeyCode::X, KeyCode::Right]);
+        run(&mut world, handle_input);
+        ground_piece(&mut world);
+        assert_ne!(piece_cells(&mut world), spawned);
+
+        next_frame(&mut world);
+        let mut input = world.resource_mut::<Input<KeyCode>>();
+        input.release(KeyCode::X);
+        input.release(KeyCode::Right);
+        input.press(KeyCode::R);
+        run(&mut world, reset_piece_to_spawn);
+        assert_eq!(piece_cells(&mut world), spawned);
+        // Turning it again starts from the spawn orientation
+        let mut fresh = game();
+        spawn_piece(&mut fresh, PieceKind::T);
+        for world in [&mut world, &mut fresh] {
+            next_frame(world);
+            press(world, &[KeyCode::X]);
+            run(world, handle_input);
+        }
+        assert_eq!(piece_cells(&mut world), piece_cells(&mut fresh));
+    }
+
+    #[test]
+    fn reset_only_works_in_practice_mode() {
+        let mut world = game();
+        world.insert_resource(PracticeMode(false));
+        world.insert_resource(ResetPieceKey(KeyCode::R));
+        spawn_piece(&mut world, PieceKind::T);
+        shift_piece(&mut world, 0, -5);
+        let moved = piece_cells(&mut world);
+        press(&mut world, &[KeyCode::R]);
+        run(&mut world, reset_piece_to_spawn);
+        assert_eq!(piece_cells(&mut world), moved);
+    }
+
+    #[test]
+    fn sonic_drop_lands_the_piece_without_locking_it() {
+        let mut world = game();
+        fill(&mut world, &full_rows(0..2));
+        spawn_piece(&mut world, PieceKind::O);
+        press(&mut world, &[KeyCode::Up]);
+        run(&mut world, handle_input);
+        let landed = piece_cells(&mut world);
+        assert_eq!(landed.iter().map(|&(_, y)| y).min(), Some(2));
+        let grid = &world.resource::<FullGrid>().0;
+        assert!(grid[2..].iter().all(|row| *row == [false; COLUMNS]));
+
+        // Still falling, so it can slide along the stack
+        next_frame(&mut world);
+        press(&mut world, &[KeyCode::Left]);
+        run(&mut world, handle_input);
+        let slid: Vec<_> = landed.iter().map(|&(x, y)| (x - 1, y)).collect();
+        assert_eq!(piece_cells(&mut world), slid);
+        assert_eq!(world.resource::<PiecesPlaced>().0, 0);
+    }
+
+    #[test]
+    fn restarting_keeps_the_mode_and_settings() {
+        let mut world = game();
+        world.insert_resource(GameMode::Blitz);
+        world.insert_resource(PieceSequenceFile(None));
+        world.insert_resource(PieceSet::Tetrominoes);
+        world.insert_resource(RngSeed(None));
+        world.insert_resource(MirrorControls(true));
+        run(&mut world, start_game);
+
+        // Play a little of the first game
+        ground_piece(&mut world);
+        fill(&mut world, &full_rows(0..3));
+        world.insert_resource(Score(500));
+        world.insert_resource(LinesCleared(3));
+        world.insert_resource(PiecesPlaced(7));
+        run(&mut world, start_game);
+
+        assert!(matches!(*world.resource::<GameMode>(), GameMode::Blitz));
+        assert!(world.resource::<MirrorControls>().0);
+        assert_eq!(world.resource::<Score>().0, 0);
+        assert_eq!(world.resource::<LinesCleared>().0, 0);
+        assert_eq!(world.resource::<PiecesPlaced>().0, 0);
+        let full_grid = world.resource::<FullGrid>();
+        assert!(full_grid.0.iter().all(|row| !row.contains(&true)));
+        // Only the new game's piece is left
+        assert_eq!(world.query::<&Tile>().iter(&world).count(), 4);
+        assert_eq!(piece_cells(&mut world).len(), 4);
+    }
+
+    /// Moves an O piece against the left wall, then presses Left
+    fn press_left_at_the_wall(wrap: bool) -> (Vec<(i8, i8)>, Vec<(i8, i8)>) {
+        let mut world = game();
+        world.insert_resource(WrapMode(wrap));
+        spawn_piece(&mut world, PieceKind::O);
+        let left_edge = piece_cells(&mut world)[0].0;
+        shift_piece(&mut world, -left_edge, 0);
+        let at_wall = piece_cells(&mut world);
+        press(&mut world, &[KeyCode::Left]);
+        run(&mut world, handle_input);
+        (at_wall, piece_cells(&mut world))
+    }
+
+    #[test]
+    fn wrap_mode_carries_a_piece_round_to_the_other_side() {
+        let (at_wall, moved) = press_left_at_the_wall(true);
+        let mut wrapped: Vec<_> = at_wall
+            .iter()
+            .map(|&(x, y)| ((x - 1).rem_euclid(COLUMNS as i8), y))
+            .collect();
+        wrapped.sort_unstable();
+        assert_eq!(moved, wrapped);
+        assert!(moved.iter().any(|&(x, _)| x == COLUMNS as i8 - 1));
+
+        let (at_wall, moved) = press_left_at_the_wall(false);
+        assert_eq!(moved, at_wall);
+    }
+
+    /// The points for a double at level 3
+    fn score_for_a_double(system: ScoringSystem) -> u32 {
+        let mut world = game();
+        world.insert_resource(ScoringConfig::new(system));
+        world.insert_resource(LinesCleared(35));
+        fill(&mut world, &full_rows(0..2));
+        run(&mut world, clear_rows);
+        world.resource::<Score>().0
+    }
+
+    #[test]
+    fn doubles_at_level_3_score_by_the_chosen_system() {
+        assert_eq!(score_for_a_double(ScoringSystem::ClassicNes), 400);
+        assert_eq!(score_for_a_double(ScoringSystem::Guideline), 300);
+    }
+
+    /// Spawns a T under a hard ceiling, with the board's top rows as given,
+    /// and says whether the game was ended
+    fn spawns_into_a_top_out(stack: &[(i8, i8)]) -> bool {
+        let mut world = game();
+        world.insert_resource(HardCeiling(true));
+        world.insert_resource(ReduceMotion(true));
+        world.insert_resource(State::new(GameState::Playing));
+        drive_state(&mut world);
+        fill(&mut world, stack);
+        spawn_piece(&mut world, PieceKind::T);
+        run(&mut world, lower_spawned_piece);
+        assert!(piece_cells(&mut world).iter().all(|&(_, y)| y < ROWS as i8));
+        drive_state(&mut world);
+        *world.resource::<State<GameState>>().current() == GameState::GameOver
+    }
+
+    #[test]
+    fn hard_ceiling_tops_out_when_the_next_piece_cant_spawn() {
+        let top = ROWS as i8 - 1;
+        assert!(spawns_into_a_top_out(&[(6, top)]));
+        assert!(spawns_into_a_top_out(&[(5, top - 1)]));
+        // Beside the spawn is still fine
+        assert!(!spawns_into_a_top_out(&[(0, top), (9, top)]));
+    }
 }
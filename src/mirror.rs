@@ -0,0 +1,69 @@
+use bevy::prelude::{Local, Query, Res, ResMut};
+
+use crate::stats::LinesCleared;
+use crate::{FullGrid, Tile, COLUMNS, ROWS};
+
+/// A chaos modifier that flips the stack left to right after every line clear.
+/// Enabled by setting the `TETRIS_MIRROR_ON_CLEAR` environment variable.
+pub(crate) struct MirrorOnClear(pub bool);
+
+/// Flips the board and its tiles once rows have been cleared
+pub(crate) fn mirror_stack_on_clear(
+    mirror_on_clear: Res<MirrorOnClear>,
+    lines: Res<LinesCleared>,
+    mut full_grid: ResMut<FullGrid>,
+    mut tiles: Query<&mut Tile>,
+    mut last_lines: Local<u32>,
+) {
+    // The count goes back to zero when a new game starts
+    let cleared = lines.0 > *last_lines;
+    *last_lines = lines.0;
+    if !mirror_on_clear.0 || !cleared {
+        return;
+    }
+    // Only the stack flips, including the piece that just locked, whose FallingSegment
+    // hasn't been removed yet. A piece that's already spawned keeps its place.
+    for mut tile in &mut tiles {
+        if (0..ROWS as i8).contains(&tile.y) && full_grid.0[tile.y as usize][tile.x as usize] {
+            tile.x = COLUMNS as i8 - 1 - tile.x;
+        }
+    }
+    for row in &mut full_grid.0[..ROWS] {
+        row.reverse();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::world::World;
+
+    use super::*;
+    use crate::testing::run;
+    use crate::tetrominoes::PieceKind;
+
+    #[test]
+    fn flips_the_stack_but_not_the_falling_piece() {
+        let mut world = World::new();
+        let mut full_grid = FullGrid::empty();
+        full_grid.0[0][0] = true;
+        full_grid.0[0][1] = true;
+        world.insert_resource(full_grid);
+        world.insert_resource(MirrorOnClear(true));
+        world.insert_resource(LinesCleared(1));
+        let stack = world.spawn().insert(Tile { x: 1, y: 0 }).id();
+        let segment = PieceKind::T.tetromino().shape[0];
+        let falling = world
+            .spawn()
+            .insert(Tile { x: 2, y: 3 })
+            .insert(segment)
+            .id();
+        run(&mut world, mirror_stack_on_clear);
+
+        assert_eq!(*world.get::<Tile>(stack).unwrap(), Tile { x: 8, y: 0 });
+        assert_eq!(*world.get::<Tile>(falling).unwrap(), Tile { x: 2, y: 3 });
+        let mut flipped = [false; COLUMNS];
+        flipped[8] = true;
+        flipped[9] = true;
+        assert_eq!(world.resource::<FullGrid>().0[0], flipped);
+    }
+}
@@ -0,0 +1,75 @@
+use bevy::prelude::{ChangeTrackers, Input, KeyCode, Query, Res, ResMut, With, Without};
+use bevy::sprite::Sprite;
+
+use crate::invisible::{Hidden, Vanish};
+use crate::{GridBackground, SpawnFade, Tile};
+
+/// How much each key press changes an opacity by
+const OPACITY_STEP: f32 = 0.1;
+
+/// How opaque the board's backdrop is, from 0 to 1, so the game can sit over
+/// a stream's webcam. Adjusted with [ and ].
+pub(crate) struct BackgroundOpacity(pub f32);
+
+/// How opaque the tiles are, from 0 to 1. Only affects how they're drawn.
+/// Adjusted with - and =.
+pub(crate) struct TileOpacity(pub f32);
+
+fn step(opacity: &mut f32, keyboard_input: &Input<KeyCode>, down: KeyCode, up: KeyCode) {
+    if keyboard_input.just_pressed(down) {
+        *opacity = (*opacity - OPACITY_STEP).clamp(0.0, 1.0);
+    }
+    if keyboard_input.just_pressed(up) {
+        *opacity = (*opacity + OPACITY_STEP).clamp(0.0, 1.0);
+    }
+}
+
+pub(crate) fn adjust_opacity(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut background_opacity: ResMut<BackgroundOpacity>,
+    mut tile_opacity: ResMut<TileOpacity>,
+) {
+    if keyboard_input.any_just_pressed([KeyCode::LBracket, KeyCode::RBracket]) {
+        step(
+            &mut background_opacity.0,
+            &keyboard_input,
+            KeyCode::LBracket,
+            KeyCode::RBracket,
+        );
+    }
+    if keyboard_input.any_just_pressed([KeyCode::Minus, KeyCode::Equals]) {
+        step(
+            &mut tile_opacity.0,
+            &keyboard_input,
+            KeyCode::Minus,
+            KeyCode::Equals,
+        );
+    }
+}
+
+pub(crate) fn apply_background_opacity(
+    opacity: Res<BackgroundOpacity>,
+    mut backgrounds: Query<&mut Sprite, With<GridBackground>>,
+) {
+    if !opacity.is_changed() {
+        return;
+    }
+    for mut sprite in &mut backgrounds {
+        sprite.color.set_a(opacity.0);
+    }
+}
+
+/// Sets the opacity of tiles that aren't fading in or out, which apply it themselves
+pub(crate) fn apply_tile_opacity(
+    opacity: Res<TileOpacity>,
+    mut tiles: Query<
+        (&mut Sprite, ChangeTrackers<Tile>),
+        (Without<SpawnFade>, Without<Vanish>, Without<Hidden>),
+    >,
+) {
+    for (mut sprite, tracker) in &mut tiles {
+        if opacity.is_changed() || tracker.is_added() {
+            sprite.color.set_a(opacity.0);
+        }
+    }
+}
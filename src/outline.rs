@@ -0,0 +1,85 @@
+use bevy::prelude::{
+    Color, Commands, Component, DespawnRecursiveExt, Entity, Input, KeyCode, Local, Query, Res,
+    ResMut, Transform, Vec3, With,
+};
+use bevy::sprite::{Sprite, SpriteBundle};
+
+use crate::{BoardLayout, FallingSegment, Tile, Z_OUTLINE};
+
+const OUTLINE: Color = Color::WHITE;
+/// The thickness of the outline, in pixels
+const OUTLINE_WIDTH: f32 = 2.0;
+
+/// Whether the falling piece is traced with a bright outline, so it stands out
+/// from the stack whatever its color. Toggled with O.
+pub(crate) struct ActivePieceOutline(pub bool);
+
+/// One edge of the falling piece's outline
+#[derive(Component)]
+pub(crate) struct OutlineEdge;
+
+pub(crate) fn toggle_outline(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut outline: ResMut<ActivePieceOutline>,
+) {
+    if keyboard_input.just_pressed(KeyCode::O) {
+        outline.0 = !outline.0;
+    }
+}
+
+/// Redraws the outline whenever the piece moves, turns, spawns or locks
+pub(crate) fn update_outline(
+    layout: Res<BoardLayout>,
+    outline: Res<ActivePieceOutline>,
+    segments: Query<&Tile, With<FallingSegment>>,
+    edges: Query<Entity, With<OutlineEdge>>,
+    mut drawn: Local<Vec<Tile>>,
+    mut commands: Commands,
+) {
+    let tiles: Vec<Tile> = if outline.0 {
+        segments.iter().copied().collect()
+    } else {
+        Vec::new()
+    };
+    if *drawn == tiles && !layout.is_changed() {
+        return;
+    }
+    edges.for_each(|entity| commands.entity(entity).despawn_recursive());
+    let size = layout.cell_size();
+    for tile in &tiles {
+        // Only the sides that don't touch another cell of the piece
+        for (dx, dy) in [(-1, 0), (1, 0), (0, -1), (0, 1)] {
+            let neighbor = Tile {
+                x: tile.x + dx,
+                y: tile.y + dy,
+            };
+            if tiles.contains(&neighbor) {
+                continue;
+            }
+            let position = layout.cell_position(
+                f32::from(tile.x) + f32::from(dx) / 2.0,
+                f32::from(tile.y) + f32::from(dy) / 2.0,
+            );
+            let scale = if dx == 0 {
+                Vec3::new(size + OUTLINE_WIDTH, OUTLINE_WIDTH, 1.0)
+            } else {
+                Vec3::new(OUTLINE_WIDTH, size + OUTLINE_WIDTH, 1.0)
+            };
+            commands
+                .spawn_bundle(SpriteBundle {
+                    sprite: Sprite {
+                        color: OUTLINE,
+                        ..Default::default()
+                    },
+                    transform: Transform {
+                        translation: position.extend(Z_OUTLINE),
+                        scale,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .insert(OutlineEdge);
+        }
+    }
+    *drawn = tiles;
+}
@@ -0,0 +1,94 @@
+use std::collections::VecDeque;
+use std::{fs, io};
+
+use bevy::log::warn;
+use bevy::prelude::{Color, Commands, Query, Res, ResMut, State, With};
+
+use crate::tetrominoes::{PieceKind, PieceSource};
+use crate::{
+    end_game, tile_sprite, EntryTimer, FallingSegment, FullGrid, GameMode, GameState, ReduceMotion,
+    Tile, COLUMNS, ROWS,
+};
+
+const PUZZLE_COLOR: Color = Color::GRAY;
+
+/// A board to clear with a fixed set of pieces
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Puzzle {
+    /// The filled cells, bottom row first
+    board: Vec<[bool; COLUMNS]>,
+    pieces: VecDeque<PieceKind>,
+}
+
+impl Puzzle {
+    /// Reads rows of `.` and `#` from the top of the board down,
+    /// followed by the pieces to deal, like `IJLOSTZ`.
+    pub fn from_file(path: &str) -> io::Result<Puzzle> {
+        let mut board = Vec::new();
+        let mut pieces = VecDeque::new();
+        for line in fs::read_to_string(path)?.lines().map(str::trim) {
+            if !line.is_empty() && line.chars().all(|c| c == '.' || c == '#') {
+                if line.len() != COLUMNS {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("Puzzle rows must be {} cells wide: {:?}", COLUMNS, line),
+                    ));
+                }
+                let mut row = [false; COLUMNS];
+                for (cell, c) in row.iter_mut().zip(line.chars()) {
+                    *cell = c == '#';
+                }
+                board.push(row);
+                continue;
+            }
+            for c in line.chars() {
+                match PieceKind::from_char(c) {
+                    Some(kind) => pieces.push_back(kind),
+                    None if c.is_whitespace() => {}
+                    None => warn!("Skipping unknown piece {:?} in {}", c, path),
+                }
+            }
+        }
+        if board.len() > ROWS {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Puzzles can be at most {} rows tall", ROWS),
+            ));
+        }
+        board.reverse();
+        Ok(Puzzle { board, pieces })
+    }
+
+    /// Fills the board with the puzzle's cells and returns its pieces
+    pub fn set_up(&self, full_grid: &mut FullGrid, commands: &mut Commands) -> PieceSource {
+        for (y, row) in self.board.iter().enumerate() {
+            full_grid.0[y] = *row;
+            for x in (0..COLUMNS).filter(|&x| row[x]) {
+                let (x, y) = (x as i8, y as i8);
+                commands
+                    .spawn()
+                    .insert_bundle(tile_sprite(PUZZLE_COLOR))
+                    .insert(Tile { x, y });
+            }
+        }
+        PieceSource::Puzzle(self.pieces.clone())
+    }
+}
+
+/// Ends a puzzle once its last piece has locked
+pub(crate) fn end_puzzle(
+    mode: Res<GameMode>,
+    piece_source: Res<PieceSource>,
+    entry_timer: Res<EntryTimer>,
+    reduce_motion: Res<ReduceMotion>,
+    segments: Query<(), With<FallingSegment>>,
+    mut game_state: ResMut<State<GameState>>,
+) {
+    // A piece dealt this frame hasn't been spawned yet
+    if !matches!(*mode, GameMode::Puzzle(_)) || piece_source.is_changed() {
+        return;
+    }
+    if piece_source.is_empty() && entry_timer.0.is_none() && segments.is_empty() {
+        end_game(&mut game_state, &reduce_motion);
+    }
+}
@@ -0,0 +1,48 @@
+use bevy::prelude::{Color, Input, KeyCode, Query, Res, ResMut, Without};
+use bevy::sprite::Sprite;
+use bevy::time::Time;
+
+use crate::{BaseColor, LockFlash, ReduceMotion, Tile};
+
+/// How many degrees the hues turn each second
+const HUE_SPEED: f32 = 90.0;
+
+/// A cosmetic mode that colors every tile by its position, cycling through the hues.
+/// Toggled with C.
+pub(crate) struct RainbowMode(pub bool);
+
+pub(crate) fn toggle_rainbow(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut rainbow: ResMut<RainbowMode>,
+) {
+    if keyboard_input.just_pressed(KeyCode::C) {
+        rainbow.0 = !rainbow.0;
+    }
+}
+
+pub(crate) fn apply_rainbow(
+    time: Res<Time>,
+    rainbow: Res<RainbowMode>,
+    reduce_motion: Res<ReduceMotion>,
+    mut tiles: Query<(&Tile, &mut Sprite, &BaseColor), Without<LockFlash>>,
+) {
+    if !rainbow.0 && !rainbow.is_changed() {
+        return;
+    }
+    // A still rainbow under reduced motion
+    let turned = if reduce_motion.0 {
+        0.0
+    } else {
+        time.seconds_since_startup() as f32 * HUE_SPEED
+    };
+    for (tile, mut sprite, base) in &mut tiles {
+        let alpha = sprite.color.a();
+        sprite.color = if rainbow.0 {
+            let hue = (f32::from(tile.x) * 36.0 + f32::from(tile.y) * 18.0 + turned) % 360.0;
+            Color::hsl(hue, 0.8, 0.6)
+        } else {
+            base.0
+        };
+        sprite.color.set_a(alpha);
+    }
+}
@@ -0,0 +1,249 @@
+use std::collections::VecDeque;
+use std::fs;
+use std::time::Duration;
+
+use bevy::log::warn;
+use bevy::prelude::{
+    AssetServer, Color, Commands, Component, DespawnRecursiveExt, Entity, Input, KeyCode, Local,
+    Query, Res, ResMut, With,
+};
+use bevy::text::Text;
+use bevy::time::Time;
+use bevy::ui::{UiRect, Val};
+
+use crate::hud::{show_hud_text, HudText};
+use crate::storage::data_file;
+use crate::{PracticeMode, Score};
+
+/// Where the session totals are kept between runs, in the data directory
+const SESSION_STATS_FILE: &str = "session_stats.txt";
+/// How many lines it takes to go up a level
+const LINES_PER_LEVEL: u32 = 10;
+/// How many of the latest pieces the downstack efficiency covers
+const EFFICIENCY_WINDOW: usize = 20;
+
+/// Lines cleared so far this game
+pub(crate) struct LinesCleared(pub u32);
+
+impl LinesCleared {
+    /// The level reached, which goes up every ten lines as on the NES
+    pub fn level(&self) -> u32 {
+        self.0 / LINES_PER_LEVEL
+    }
+}
+
+/// Pieces locked so far this game
+pub(crate) struct PiecesPlaced(pub u32);
+
+/// How long this game has been played, not counting the game over animation
+pub(crate) struct PlayTime(pub Duration);
+
+pub(crate) fn track_play_time(time: Res<Time>, mut play_time: ResMut<PlayTime>) {
+    play_time.0 += time.delta();
+}
+
+/// Totals across every game played, kept between runs in the data directory
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct SessionStats {
+    pub games_played: u32,
+    pub total_lines: u32,
+    pub total_pieces: u32,
+    pub best_score: u32,
+}
+
+impl SessionStats {
+    /// Reads the totals saved by the last run
+    pub fn load() -> SessionStats {
+        let path = data_file(SESSION_STATS_FILE);
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(_) => return SessionStats::default(),
+        };
+        SessionStats::parse(&text).unwrap_or_else(|| {
+            warn!("Ignoring malformed session stats in {}", path.display());
+            SessionStats::default()
+        })
+    }
+
+    fn save(&self) {
+        let path = data_file(SESSION_STATS_FILE);
+        if let Err(err) = fs::write(&path, self.to_text()) {
+            warn!(
+                "Couldn't save the session stats to {}: {}",
+                path.display(),
+                err
+            );
+        }
+    }
+
+    /// Written as `games lines pieces best_score`
+    fn to_text(&self) -> String {
+        format!(
+            "{} {} {} {}\n",
+            self.games_played, self.total_lines, self.total_pieces, self.best_score
+        )
+    }
+
+    fn parse(text: &str) -> Option<SessionStats> {
+        let values: Result<Vec<u32>, _> = text.split_whitespace().map(str::parse).collect();
+        match values.ok()?.as_slice() {
+            &[games_played, total_lines, total_pieces, best_score] => Some(SessionStats {
+                games_played,
+                total_lines,
+                total_pieces,
+                best_score,
+            }),
+            _ => None,
+        }
+    }
+}
+
+pub(crate) fn record_session_stats(
+    score: Res<Score>,
+    lines: Res<LinesCleared>,
+    pieces: Res<PiecesPlaced>,
+    mut session: ResMut<SessionStats>,
+) {
+    session.games_played += 1;
+    session.total_lines += lines.0;
+    session.total_pieces += pieces.0;
+    session.best_score = session.best_score.max(score.0);
+    session.save();
+}
+
+pub(crate) fn reset_session_stats(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut session: ResMut<SessionStats>,
+) {
+    if keyboard_input.just_pressed(KeyCode::F9) {
+        *session = SessionStats::default();
+        session.save();
+    }
+}
+
+/// The lines cleared by each of the latest pieces, oldest first
+#[derive(Default)]
+pub(crate) struct RecentPieces(VecDeque<u32>);
+
+/// Lines cleared per piece, or `None` before any pieces are placed
+fn downstack_efficiency(lines_per_piece: &VecDeque<u32>) -> Option<f32> {
+    let pieces = lines_per_piece.len();
+    (pieces != 0).then(|| lines_per_piece.iter().sum::<u32>() as f32 / pieces as f32)
+}
+
+pub(crate) fn track_recent_pieces(
+    lines: Res<LinesCleared>,
+    pieces: Res<PiecesPlaced>,
+    mut recent: ResMut<RecentPieces>,
+    mut last: Local<(u32, u32)>,
+) {
+    // Both counters go back to zero when a new game starts
+    let (last_lines, last_pieces) = *last;
+    for _ in last_pieces..pieces.0 {
+        if recent.0.len() == EFFICIENCY_WINDOW {
+            recent.0.pop_front();
+        }
+        recent.0.push_back(0);
+    }
+    if let Some(latest) = recent.0.back_mut() {
+        *latest += lines.0.saturating_sub(last_lines);
+    }
+    *last = (lines.0, pieces.0);
+}
+
+#[derive(Component)]
+pub(crate) struct EfficiencyText;
+
+pub(crate) fn show_downstack_efficiency(
+    practice_mode: Res<PracticeMode>,
+    recent: Res<RecentPieces>,
+    asset_server: Res<AssetServer>,
+    mut texts: Query<(Entity, &mut Text), With<EfficiencyText>>,
+    mut commands: Commands,
+) {
+    if !practice_mode.0 {
+        texts.for_each(|(entity, _)| commands.entity(entity).despawn_recursive());
+        return;
+    }
+    let value = match downstack_efficiency(&recent.0) {
+        Some(efficiency) => format!(
+            "Lines per piece: {:.2} (last {})",
+            efficiency,
+            recent.0.len()
+        ),
+        None => "Lines per piece: place a piece".to_string(),
+    };
+    let hud = HudText {
+        value,
+        color: Color::WHITE,
+        font_size: 16.0,
+        position: UiRect {
+            left: Val::Px(10.0),
+            bottom: Val::Px(10.0),
+            ..Default::default()
+        },
+    };
+    let existing = texts.get_single_mut().ok().map(|(_, text)| text);
+    show_hud_text(existing, hud, EfficiencyText, &asset_server, &mut commands);
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::schedule::{Stage, SystemStage};
+    use bevy::ecs::world::World;
+
+    use super::*;
+    use crate::testing::run;
+
+    #[test]
+    fn session_stats_survive_saving_and_loading() {
+        let stats = SessionStats {
+            games_played: 3,
+            total_lines: 41,
+            total_pieces: 120,
+            best_score: 9800,
+        };
+        assert_eq!(SessionStats::parse(&stats.to_text()), Some(stats));
+        assert_eq!(SessionStats::parse("3 41 120"), None);
+        assert_eq!(SessionStats::parse("3 41 lots 9800"), None);
+    }
+
+    #[test]
+    fn efficiency_is_lines_per_piece() {
+        assert_eq!(downstack_efficiency(&VecDeque::new()), None);
+        let recent: VecDeque<u32> = [0, 1, 0, 4].into_iter().collect();
+        assert_eq!(downstack_efficiency(&recent), Some(1.25));
+    }
+
+    #[test]
+    fn lines_go_to_the_piece_that_cleared_them() {
+        let mut world = World::new();
+        world.insert_resource(LinesCleared(0));
+        world.insert_resource(PiecesPlaced(0));
+        world.insert_resource(RecentPieces::default());
+        // One stage throughout, so the tracker remembers the last counts
+        let mut stage = SystemStage::single_threaded();
+        stage.add_system(track_recent_pieces);
+        let mut play = |pieces: u32, lines: u32| {
+            world.resource_mut::<PiecesPlaced>().0 += pieces;
+            world.resource_mut::<LinesCleared>().0 += lines;
+            stage.run(&mut world);
+            Vec::from(world.resource::<RecentPieces>().0.clone())
+        };
+
+        assert!(play(0, 0).is_empty());
+        assert_eq!(play(2, 0), [0, 0]);
+        assert_eq!(play(0, 3), [0, 3]);
+        assert_eq!(play(1, 1), [0, 3, 1]);
+    }
+
+    #[test]
+    fn only_the_latest_pieces_count() {
+        let mut world = World::new();
+        world.insert_resource(LinesCleared(0));
+        world.insert_resource(PiecesPlaced(EFFICIENCY_WINDOW as u32 + 5));
+        world.insert_resource(RecentPieces::default());
+        run(&mut world, track_recent_pieces);
+        assert_eq!(world.resource::<RecentPieces>().0.len(), EFFICIENCY_WINDOW);
+    }
+}
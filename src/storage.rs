@@ -0,0 +1,28 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+/// Where saved settings, stats and exported boards go: `TETRIS_DATA_DIR` if it's set,
+/// otherwise the platform's per-user data directory, falling back to the working
+/// directory if there isn't one.
+fn data_dir() -> PathBuf {
+    if let Some(dir) = env::var_os("TETRIS_DATA_DIR") {
+        return PathBuf::from(dir);
+    }
+    let base = env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))
+        .or_else(|| env::var_os("APPDATA").map(PathBuf::from));
+    match base {
+        Some(base) => base.join("tetris-bevy"),
+        None => PathBuf::new(),
+    }
+}
+
+/// The path to a file in the data directory, which is created if it doesn't exist yet.
+/// Failing to create it is left for reading or writing the file to report.
+pub(crate) fn data_file(name: &str) -> PathBuf {
+    let dir = data_dir();
+    let _ = fs::create_dir_all(&dir);
+    dir.join(name)
+}
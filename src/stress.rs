@@ -0,0 +1,114 @@
+use std::time::Duration;
+
+use bevy::app::{App, AppExit, Plugin};
+use bevy::diagnostic::{Diagnostics, FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin};
+use bevy::log::info;
+use bevy::prelude::{Color, Commands, CoreStage, EventReader, Query, Res, ResMut, State, With};
+
+use crate::{tile_sprite, FallingSegment, FullGrid, GameState, Tile, COLUMNS, ROWS};
+
+/// How many rows are kept filled, leaving room for the falling pieces above
+const FILLED_ROWS: usize = ROWS - 4;
+/// The column left open in the upper rows so that only the bottom four rows clear
+const HOLE: usize = COLUMNS - 1;
+
+/// Keeps the board packed with tiles, clearing and refilling four rows
+/// every frame, and reports frame times when the app exits.
+/// Enabled by setting the `TETRIS_STRESS` environment variable.
+pub(crate) struct StressTestPlugin;
+
+impl Plugin for StressTestPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugin(FrameTimeDiagnosticsPlugin)
+            .add_plugin(LogDiagnosticsPlugin::default())
+            .init_resource::<FrameTimes>()
+            // Before the frame's gameplay, so the tiles have been spawned by the time rows clear
+            .add_system_to_stage(CoreStage::PreUpdate, stress_fill)
+            .add_system_to_stage(CoreStage::Last, record_frame_time)
+            .add_system_to_stage(CoreStage::Last, report_frame_times);
+    }
+}
+
+#[derive(Default)]
+struct FrameTimes {
+    frames: u32,
+    total: Duration,
+    worst: Duration,
+}
+
+fn stress_fill(
+    game_state: Res<State<GameState>>,
+    mut full_grid: ResMut<FullGrid>,
+    falling: Query<&Tile, With<FallingSegment>>,
+    mut commands: Commands,
+) {
+    if *game_state.current() != GameState::Playing {
+        return;
+    }
+    let falling: Vec<Tile> = falling.iter().copied().collect();
+    for y in 0..FILLED_ROWS {
+        for x in 0..COLUMNS {
+            if full_grid.0[y][x] || (x == HOLE && y >= 4) {
+                continue;
+            }
+            let tile = Tile {
+                x: x as i8,
+                y: y as i8,
+            };
+            if falling.contains(&tile) {
+                continue;
+            }
+            full_grid.0[y][x] = true;
+            commands
+                .spawn()
+                .insert_bundle(tile_sprite(Color::SILVER))
+                .insert(tile);
+        }
+    }
+}
+
+fn record_frame_time(diagnostics: Res<Diagnostics>, mut frame_times: ResMut<FrameTimes>) {
+    let frame_time = diagnostics
+        .get(FrameTimeDiagnosticsPlugin::FRAME_TIME)
+        .and_then(|diagnostic| diagnostic.value());
+    if let Some(seconds) = frame_time {
+        let frame_time = Duration::from_secs_f64(seconds);
+        frame_times.frames += 1;
+        frame_times.total += frame_time;
+        frame_times.worst = frame_times.worst.max(frame_time);
+    }
+}
+
+fn report_frame_times(mut exits: EventReader<AppExit>, frame_times: Res<FrameTimes>) {
+    if exits.iter().next().is_none() || frame_times.frames == 0 {
+        return;
+    }
+    info!(
+        "Stress test: {} frames, average {:.2}ms, worst {:.2}ms",
+        frame_times.frames,
+        (frame_times.total / frame_times.frames).as_secs_f64() * 1000.0,
+        frame_times.worst.as_secs_f64() * 1000.0
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{game, piece_cells, run, shift_piece, spawn_piece};
+    use crate::tetrominoes::PieceKind;
+
+    #[test]
+    fn leaves_the_falling_piece_alone() {
+        let mut world = game();
+        world.insert_resource(State::new(GameState::Playing));
+        spawn_piece(&mut world, PieceKind::O);
+        shift_piece(&mut world, 0, -(ROWS as i8));
+        let piece = piece_cells(&mut world);
+        run(&mut world, stress_fill);
+        let full_grid = world.resource::<FullGrid>();
+        for (x, y) in piece {
+            assert!(!full_grid.0[y as usize][x as usize]);
+        }
+        assert!(full_grid.0[0][0]);
+    }
+}
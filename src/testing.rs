@@ -0,0 +1,138 @@
+//! Helpers for running the game's systems against a bare world in tests
+
+use std::time::Duration;
+
+use bevy::ecs::schedule::{IntoSystemDescriptor, Stage, SystemStage};
+use bevy::ecs::world::World;
+use bevy::prelude::{Commands, Input, KeyCode, State, With};
+use bevy::time::{Time, Timer};
+use rand::rngs::SmallRng;
+use rand::SeedableRng;
+
+use crate::ceiling::HardCeiling;
+use crate::garbage::GarbageRows;
+use crate::gravity::GravityMode;
+use crate::latency::{LatencyProbe, LatencyTest};
+use crate::stats::{LinesCleared, PiecesPlaced};
+use crate::tetrominoes::{PieceKind, PieceSet, PieceSource};
+use crate::wrap::WrapMode;
+use crate::{
+    compute_drop_distance, spawn, BufferedInput, CollapseAnimation, EntryDelay, EntryTimer,
+    FallTimer, FallingSegment, FullGrid, GameState, MirrorControls, NaturalFall, ReduceMotion,
+    Score, ScoringConfig, ScoringSystem, SoftDropLocks, Tile, FALL_INTERVAL,
+};
+
+/// A game in progress on an empty board, with no piece falling yet
+pub(crate) fn game() -> World {
+    let mut world = World::new();
+    let mut time = Time::default();
+    time.update_with_instant(time.startup());
+    world.insert_resource(time);
+    world.insert_resource(Input::<KeyCode>::default());
+    world.insert_resource(SmallRng::seed_from_u64(0));
+    world.insert_resource(FullGrid::empty());
+    world.insert_resource(FallTimer(Timer::new(FALL_INTERVAL, true)));
+    world.insert_resource(NaturalFall {
+        timer: Timer::new(FALL_INTERVAL, true),
+        steps: 0,
+    });
+    world.insert_resource(EntryDelay(Duration::ZERO));
+    world.insert_resource(EntryTimer(None));
+    world.insert_resource(PieceSource::Randomizer(PieceSet::Tetrominoes));
+    world.insert_resource(PiecesPlaced(0));
+    world.insert_resource(SoftDropLocks(false));
+    world.insert_resource(Score(0));
+    world.insert_resource(ScoringConfig::new(ScoringSystem::Guideline));
+    world.insert_resource(BufferedInput::default());
+    world.insert_resource(LatencyTest(false));
+    world.insert_resource(LatencyProbe::default());
+    world.insert_resource(MirrorControls(false));
+    world.insert_resource(WrapMode(false));
+    world.insert_resource(HardCeiling(false));
+    world.insert_resource(LinesCleared(0));
+    world.insert_resource(GarbageRows(0));
+    world.insert_resource(CollapseAnimation(true));
+    world.insert_resource(ReduceMotion(false));
+    world.insert_resource(GravityMode::Naive);
+    world
+}
+
+/// Runs a system once
+pub(crate) fn run<Params>(world: &mut World, system: impl IntoSystemDescriptor<Params>) {
+    let mut stage = SystemStage::single_threaded();
+    stage.add_system(system);
+    stage.run(world);
+}
+
+/// Applies any queued state change, as the app does between frames
+pub(crate) fn drive_state(world: &mut World) {
+    let mut stage = SystemStage::single_threaded();
+    stage.add_system_set(State::<GameState>::get_driver());
+    stage.run(world);
+}
+
+/// Moves the clock on, as if a frame took `delta`
+pub(crate) fn advance(world: &mut World, delta: Duration) {
+    let mut time = world.resource_mut::<Time>();
+    let now = time.last_update().unwrap() + delta;
+    time.update_with_instant(now);
+}
+
+pub(crate) fn spawn_piece(world: &mut World, kind: PieceKind) {
+    run(world, move |mut commands: Commands| {
+        spawn(&mut commands, kind.tetromino())
+    });
+}
+
+/// The falling piece's cells, sorted
+pub(crate) fn piece_cells(world: &mut World) -> Vec<(i8, i8)> {
+    let mut cells: Vec<_> = world
+        .query_filtered::<&Tile, With<FallingSegment>>()
+        .iter(world)
+        .map(|tile| (tile.x, tile.y))
+        .collect();
+    cells.sort_unstable();
+    cells
+}
+
+/// Moves the falling piece by `(dx, dy)`, ignoring the board
+pub(crate) fn shift_piece(world: &mut World, dx: i8, dy: i8) {
+    for mut tile in world
+        .query_filtered::<&mut Tile, With<FallingSegment>>()
+        .iter_mut(world)
+    {
+        tile.x += dx;
+        tile.y += dy;
+    }
+}
+
+/// Drops the falling piece onto whatever is below it, without locking it
+pub(crate) fn ground_piece(world: &mut World) {
+    let tiles: Vec<Tile> = piece_cells(world)
+        .into_iter()
+        .map(|(x, y)| Tile { x, y })
+        .collect();
+    let hard_ceiling = world.resource::<HardCeiling>().0;
+    let distance = compute_drop_distance(&tiles, hard_ceiling, world.resource::<FullGrid>());
+    shift_piece(world, 0, -distance);
+}
+
+/// Starts a new frame, so presses from the last one are no longer just pressed
+pub(crate) fn next_frame(world: &mut World) {
+    world.resource_mut::<Input<KeyCode>>().clear();
+}
+
+pub(crate) fn press(world: &mut World, keys: &[KeyCode]) {
+    let mut input = world.resource_mut::<Input<KeyCode>>();
+    for &key in keys {
+        input.press(key);
+    }
+}
+
+/// Fills the given cells of the board with locked tiles
+pub(crate) fn fill(world: &mut World, cells: &[(i8, i8)]) {
+    for &(x, y) in cells {
+        world.resource_mut::<FullGrid>().0[y as usize][x as usize] = true;
+        world.spawn().insert(Tile { x, y });
+    }
+}
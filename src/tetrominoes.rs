@@ -1,165 +1,502 @@
+use std::collections::VecDeque;
+use std::{fs, io};
+
+use bevy::log::warn;
 use bevy::prelude::Color;
 use rand::{rngs::SmallRng, seq::SliceRandom};
 
-use crate::FallingSegment;
+use crate::{FallingSegment, Pivot};
 
 #[derive(Clone, Copy)]
-pub(crate) struct Tetromino {
-    pub shape: [FallingSegment; 4],
+pub(crate) struct Piece {
+    /// Each cell's offset from the piece's focal point
+    pub shape: &'static [FallingSegment],
     pub color: Color,
 }
 
-const I: Tetromino = Tetromino {
-    shape: [
-        FallingSegment {
-            x_offset: 0,
-            y_offset: 0,
-        },
-        FallingSegment {
-            x_offset: -1,
-            y_offset: 0,
-        },
-        FallingSegment {
-            x_offset: 1,
-            y_offset: 0,
-        },
-        FallingSegment {
-            x_offset: 2,
-            y_offset: 0,
-        },
+/// A cell of a piece that turns about its focal point
+const fn segment(x_offset: i8, y_offset: i8) -> FallingSegment {
+    FallingSegment {
+        x_offset,
+        y_offset,
+        pivot: Pivot::Cell,
+    }
+}
+
+/// A cell of a piece that turns about the corner up and to the right of its focal point
+const fn corner_segment(x_offset: i8, y_offset: i8) -> FallingSegment {
+    FallingSegment {
+        x_offset,
+        y_offset,
+        pivot: Pivot::Corner,
+    }
+}
+
+const I: Piece = Piece {
+    shape: &[
+        corner_segment(0, 1),
+        corner_segment(-1, 1),
+        corner_segment(1, 1),
+        corner_segment(2, 1),
     ],
     color: Color::TEAL,
 };
-const T: Tetromino = Tetromino {
-    shape: [
-        FallingSegment {
-            x_offset: 0,
-            y_offset: 0,
-        },
-        FallingSegment {
-            x_offset: -1,
-            y_offset: 0,
-        },
-        FallingSegment {
-            x_offset: 1,
-            y_offset: 0,
-        },
-        FallingSegment {
-            x_offset: 0,
-            y_offset: 1,
-        },
-    ],
+const T: Piece = Piece {
+    shape: &[segment(0, 0), segment(-1, 0), segment(1, 0), segment(0, 1)],
     color: Color::PURPLE,
 };
-const J: Tetromino = Tetromino {
-    shape: [
-        FallingSegment {
-            x_offset: 0,
-            y_offset: 0,
-        },
-        FallingSegment {
-            x_offset: -1,
-            y_offset: 0,
-        },
-        FallingSegment {
-            x_offset: 1,
-            y_offset: 0,
-        },
-        FallingSegment {
-            x_offset: 1,
-            y_offset: 1,
-        },
-    ],
+const J: Piece = Piece {
+    shape: &[segment(0, 0), segment(-1, 0), segment(1, 0), segment(1, 1)],
     color: Color::ORANGE,
 };
-const L: Tetromino = Tetromino {
-    shape: [
-        FallingSegment {
-            x_offset: 0,
-            y_offset: 0,
-        },
-        FallingSegment {
-            x_offset: -1,
-            y_offset: 0,
-        },
-        FallingSegment {
-            x_offset: -1,
-            y_offset: 1,
-        },
-        FallingSegment {
-            x_offset: 1,
-            y_offset: 0,
-        },
-    ],
+const L: Piece = Piece {
+    shape: &[segment(0, 0), segment(-1, 0), segment(-1, 1), segment(1, 0)],
     color: Color::BLUE,
 };
-
-const Z: Tetromino = Tetromino {
-    shape: [
-        FallingSegment {
-            x_offset: 0,
-            y_offset: 0,
-        },
-        FallingSegment {
-            x_offset: 0,
-            y_offset: 1,
-        },
-        FallingSegment {
-            x_offset: -1,
-            y_offset: 1,
-        },
-        FallingSegment {
-            x_offset: 1,
-            y_offset: 0,
-        },
-    ],
+const Z: Piece = Piece {
+    shape: &[segment(0, 0), segment(0, 1), segment(-1, 1), segment(1, 0)],
     color: Color::RED,
 };
-
-const S: Tetromino = Tetromino {
-    shape: [
-        FallingSegment {
-            x_offset: 0,
-            y_offset: 0,
-        },
-        FallingSegment {
-            x_offset: 0,
-            y_offset: 1,
-        },
-        FallingSegment {
-            x_offset: -1,
-            y_offset: 0,
-        },
-        FallingSegment {
-            x_offset: 1,
-            y_offset: 1,
-        },
-    ],
+const S: Piece = Piece {
+    shape: &[segment(0, 0), segment(0, 1), segment(-1, 0), segment(1, 1)],
     color: Color::GREEN,
 };
-const O: Tetromino = Tetromino {
-    shape: [
-        FallingSegment {
-            x_offset: 0,
-            y_offset: 0,
-        },
-        FallingSegment {
-            x_offset: 0,
-            y_offset: 1,
-        },
-        FallingSegment {
-            x_offset: -1,
-            y_offset: 0,
-        },
-        FallingSegment {
-            x_offset: -1,
-            y_offset: 1,
-        },
+const O: Piece = Piece {
+    shape: &[
+        corner_segment(0, 0),
+        corner_segment(0, 1),
+        corner_segment(1, 0),
+        corner_segment(1, 1),
     ],
     color: Color::YELLOW,
 };
-impl Tetromino {
-    pub fn random(rng: &mut SmallRng) -> Tetromino {
-        *[I, T, L, J, S, Z, O].choose(rng).unwrap()
+
+const PENTOMINOES: [Piece; 12] = [
+    Piece {
+        shape: &[
+            segment(0, 0),
+            segment(0, 1),
+            segment(1, 1),
+            segment(-1, 0),
+            segment(0, -1),
+        ],
+        color: Color::CRIMSON,
+    },
+    Piece {
+        shape: &[
+            segment(0, 0),
+            segment(-2, 0),
+            segment(-1, 0),
+            segment(1, 0),
+            segment(2, 0),
+        ],
+        color: Color::TURQUOISE,
+    },
+    Piece {
+        shape: &[
+            segment(0, 0),
+            segment(-1, 0),
+            segment(1, 0),
+            segment(2, 0),
+            segment(2, 1),
+        ],
+        color: Color::ORANGE_RED,
+    },
+    Piece {
+        shape: &[
+            segment(0, 0),
+            segment(-1, 0),
+            segment(-2, 0),
+            segment(0, 1),
+            segment(1, 1),
+        ],
+        color: Color::MIDNIGHT_BLUE,
+    },
+    Piece {
+        shape: &[
+            segment(0, 0),
+            segment(1, 0),
+            segment(0, 1),
+            segment(1, 1),
+            segment(-1, 0),
+        ],
+        color: Color::PINK,
+    },
+    Piece {
+        shape: &[
+            segment(0, 0),
+            segment(-1, 1),
+            segment(0, 1),
+            segment(1, 1),
+            segment(0, -1),
+        ],
+        color: Color::VIOLET,
+    },
+    Piece {
+        shape: &[
+            segment(0, 0),
+            segment(-1, 0),
+            segment(1, 0),
+            segment(-1, 1),
+            segment(1, 1),
+        ],
+        color: Color::GOLD,
+    },
+    Piece {
+        shape: &[
+            segment(0, 0),
+            segment(-1, 0),
+            segment(1, 0),
+            segment(-1, 1),
+            segment(-1, 2),
+        ],
+        color: Color::SEA_GREEN,
+    },
+    Piece {
+        shape: &[
+            segment(0, 0),
+            segment(-1, 0),
+            segment(-1, 1),
+            segment(0, -1),
+            segment(1, -1),
+        ],
+        color: Color::SALMON,
+    },
+    Piece {
+        shape: &[
+            segment(0, 0),
+            segment(1, 0),
+            segment(-1, 0),
+            segment(0, 1),
+            segment(0, -1),
+        ],
+        color: Color::BEIGE,
+    },
+    Piece {
+        shape: &[
+            segment(0, 0),
+            segment(-1, 0),
+            segment(1, 0),
+            segment(2, 0),
+            segment(0, 1),
+        ],
+        color: Color::LIME_GREEN,
+    },
+    Piece {
+        shape: &[
+            segment(0, 0),
+            segment(0, 1),
+            segment(-1, 1),
+            segment(0, -1),
+            segment(1, -1),
+        ],
+        color: Color::FUCHSIA,
+    },
+];
+
+/// Which pieces the randomizer deals
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PieceSet {
+    Tetrominoes,
+    /// The twelve five-cell pieces
+    Pentominoes,
+}
+
+impl PieceSet {
+    /// Pentominoes if `TETRIS_PIECE_SET` is `pentominoes`
+    pub fn from_env() -> PieceSet {
+        match std::env::var("TETRIS_PIECE_SET").as_deref() {
+            Ok("pentominoes") => PieceSet::Pentominoes,
+            _ => PieceSet::Tetrominoes,
+        }
+    }
+
+    fn pieces(self) -> &'static [Piece] {
+        match self {
+            PieceSet::Tetrominoes => &[I, T, L, J, S, Z, O],
+            PieceSet::Pentominoes => &PENTOMINOES,
+        }
+    }
+
+    pub fn random(self, rng: &mut SmallRng) -> Piece {
+        *self.pieces().choose(rng).unwrap()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PieceKind {
+    I,
+    T,
+    J,
+    L,
+    Z,
+    S,
+    O,
+}
+
+impl PieceKind {
+    pub fn from_char(c: char) -> Option<PieceKind> {
+        match c.to_ascii_uppercase() {
+            'I' => Some(PieceKind::I),
+            'T' => Some(PieceKind::T),
+            'J' => Some(PieceKind::J),
+            'L' => Some(PieceKind::L),
+            'Z' => Some(PieceKind::Z),
+            'S' => Some(PieceKind::S),
+            'O' => Some(PieceKind::O),
+            _ => None,
+        }
+    }
+
+    pub fn tetromino(self) -> Piece {
+        match self {
+            PieceKind::I => I,
+            PieceKind::T => T,
+            PieceKind::J => J,
+            PieceKind::L => L,
+            PieceKind::Z => Z,
+            PieceKind::S => S,
+            PieceKind::O => O,
+        }
+    }
+}
+
+/// Where the next piece comes from
+pub(crate) enum PieceSource {
+    Randomizer(PieceSet),
+    /// Pieces dealt in order, falling back to the randomizer once they run out
+    Fixed(VecDeque<PieceKind>, PieceSet),
+    /// A puzzle's pieces, after which no more are dealt
+    Puzzle(VecDeque<PieceKind>),
+}
+
+impl PieceSource {
+    /// Reads a sequence like `IJLOSTZ` from a file, skipping whitespace.
+    /// Unknown characters are skipped with a warning.
+    pub fn from_file(path: &str, set: PieceSet) -> io::Result<PieceSource> {
+        let mut pieces = VecDeque::new();
+        for c in fs::read_to_string(path)?.chars() {
+            match PieceKind::from_char(c) {
+                Some(kind) => pieces.push_back(kind),
+                None if c.is_whitespace() => {}
+                None => warn!("Skipping unknown piece {:?} in {}", c, path),
+            }
+        }
+        Ok(PieceSource::Fixed(pieces, set))
+    }
+
+    /// The piece to deal next, or `None` if a puzzle has run out
+    pub fn next(&mut self, rng: &mut SmallRng) -> Option<Piece> {
+        match self {
+            PieceSource::Fixed(pieces, set) => Some(
+                pieces
+                    .pop_front()
+                    .map_or_else(|| set.random(rng), PieceKind::tetromino),
+            ),
+            PieceSource::Randomizer(set) => Some(set.random(rng)),
+            PieceSource::Puzzle(pieces) => pieces.pop_front().map(PieceKind::tetromino),
+        }
+    }
+
+    /// Whether every piece has been dealt
+    pub fn is_empty(&self) -> bool {
+        matches!(self, PieceSource::Puzzle(pieces) if pieces.is_empty())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::BUFFER_ROWS;
+
+    /// How many cells each piece in the set has
+    fn cells(set: PieceSet) -> usize {
+        match set {
+            PieceSet::Tetrominoes => 4,
+            PieceSet::Pentominoes => 5,
+        }
+    }
+
+    /// Why a piece's shape is malformed, if it is
+    fn shape_error(piece: &Piece, cells: usize) -> Option<String> {
+        let offsets: Vec<(i8, i8)> = piece
+            .shape
+            .iter()
+            .map(|segment| (segment.x_offset, segment.y_offset))
+            .collect();
+        if offsets.len() != cells {
+            return Some(format!("has {} cells, not {}", offsets.len(), cells));
+        }
+        if (1..offsets.len()).any(|i| offsets[..i].contains(&offsets[i])) {
+            return Some("has a repeated cell".to_string());
+        }
+        // Pieces that turn about a corner have no cell at their focal point
+        let turns_about_cell = piece
+            .shape
+            .iter()
+            .all(|segment| segment.pivot == Pivot::Cell);
+        if turns_about_cell && !offsets.contains(&(0, 0)) {
+            return Some("has no cell at its focal point".to_string());
+        }
+        // Flood fills from the first cell to check they're all connected
+        let mut reached = vec![offsets[0]];
+        let mut frontier = vec![offsets[0]];
+        while let Some((x, y)) = frontier.pop() {
+            for neighbor in [(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)] {
+                if offsets.contains(&neighbor) && !reached.contains(&neighbor) {
+                    reached.push(neighbor);
+                    frontier.push(neighbor);
+                }
+            }
+        }
+        (reached.len() != offsets.len()).then(|| "isn't connected".to_string())
+    }
+
+    /// The piece's offsets after applying `rotation` `turns` times
+    fn turned(
+        piece: &Piece,
+        rotation: fn(FallingSegment) -> FallingSegment,
+        turns: usize,
+    ) -> Vec<(i8, i8)> {
+        piece
+            .shape
+            .iter()
+            .map(|&segment| {
+                let segment = (0..turns).fold(segment, |segment, _| rotation(segment));
+                (segment.x_offset, segment.y_offset)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn pieces_are_well_formed() {
+        for set in [PieceSet::Tetrominoes, PieceSet::Pentominoes] {
+            for (i, piece) in set.pieces().iter().enumerate() {
+                if let Some(error) = shape_error(piece, cells(set)) {
+                    panic!("Piece {} of the {:?} {}", i, set, error);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn pentominoes_come_back_after_four_turns() {
+        for piece in &PENTOMINOES {
+            let original = turned(piece, FallingSegment::rotate_clockwise, 0);
+            assert_eq!(turned(piece, FallingSegment::rotate_clockwise, 4), original);
+            assert_eq!(
+                turned(piece, FallingSegment::rotate_counterclockwise, 4),
+                original
+            );
+        }
+    }
+
+    #[test]
+    fn pentomino_turns_undo_each_other() {
+        for piece in &PENTOMINOES {
+            for &segment in piece.shape {
+                assert!(segment.rotate_clockwise().rotate_counterclockwise() == segment);
+            }
+        }
+    }
+
+    #[test]
+    fn pentominoes_keep_their_shape_when_turned() {
+        for (i, piece) in PENTOMINOES.iter().enumerate() {
+            for turns in 1..4 {
+                let shape: Vec<FallingSegment> = piece
+                    .shape
+                    .iter()
+                    .map(|&segment| (0..turns).fold(segment, |s, _| s.rotate_clockwise()))
+                    .collect();
+                let turned = Piece {
+                    shape: Box::leak(shape.into_boxed_slice()),
+                    color: piece.color,
+                };
+                assert_eq!(
+                    shape_error(&turned, 5),
+                    None,
+                    "piece {} turned {} times",
+                    i,
+                    turns
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn five_cell_i_stands_up_about_its_middle() {
+        let mut standing = turned(&PENTOMINOES[1], FallingSegment::rotate_clockwise, 1);
+        standing.sort_unstable();
+        assert_eq!(standing, [(0, -2), (0, -1), (0, 0), (0, 1), (0, 2)]);
+    }
+
+    #[test]
+    fn every_piece_fits_in_the_buffer_rows() {
+        for set in [PieceSet::Tetrominoes, PieceSet::Pentominoes] {
+            for piece in set.pieces() {
+                for turns in 0..4 {
+                    let rows = turned(piece, FallingSegment::rotate_clockwise, turns)
+                        .iter()
+                        .map(|&(_, y)| y)
+                        .collect::<Vec<_>>();
+                    let height = rows.iter().max().unwrap() - rows.iter().min().unwrap() + 1;
+                    assert!(height as usize <= BUFFER_ROWS);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn i_turns_through_the_guideline_states() {
+        // The cells of each state in the guideline's 4x4 box, counting from its bottom
+        // left: spawn, then each clockwise turn
+        let guideline = [
+            [(0, 2), (1, 2), (2, 2), (3, 2)],
+            [(2, 0), (2, 1), (2, 2), (2, 3)],
+            [(0, 1), (1, 1), (2, 1), (3, 1)],
+            [(1, 0), (1, 1), (1, 2), (1, 3)],
+        ];
+        // The focal point is the box's second column and second row
+        let in_box = |offsets: Vec<(i8, i8)>| {
+            let mut cells: Vec<_> = offsets.into_iter().map(|(x, y)| (x + 1, y + 1)).collect();
+            cells.sort_unstable();
+            cells
+        };
+        for (turns, state) in guideline.iter().enumerate() {
+            let clockwise = turned(&I, FallingSegment::rotate_clockwise, turns);
+            assert_eq!(in_box(clockwise), state);
+            let counterclockwise = turned(&I, FallingSegment::rotate_counterclockwise, 4 - turns);
+            assert_eq!(in_box(counterclockwise), state);
+        }
+    }
+
+    #[test]
+    fn o_stays_put_when_turned() {
+        let mut original = turned(&O, FallingSegment::rotate_clockwise, 0);
+        original.sort_unstable();
+        for turns in 1..4 {
+            let mut cells = turned(&O, FallingSegment::rotate_clockwise, turns);
+            cells.sort_unstable();
+            assert_eq!(cells, original);
+        }
+    }
+
+    #[test]
+    fn fixed_pieces_are_dealt_before_random_ones() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let sequence = VecDeque::from([PieceKind::T, PieceKind::I, PieceKind::T]);
+        let mut source = PieceSource::Fixed(sequence.clone(), PieceSet::Pentominoes);
+        for kind in sequence {
+            let piece = source.next(&mut rng).unwrap();
+            assert!(piece.shape == kind.tetromino().shape);
+        }
+        // Only the randomizer deals pentominoes
+        for _ in 0..10 {
+            assert_eq!(source.next(&mut rng).unwrap().shape.len(), 5);
+        }
+        assert!(!source.is_empty());
     }
 }
@@ -0,0 +1,96 @@
+use std::time::Duration;
+
+use bevy::prelude::{
+    Added, Changed, Color, Commands, Component, DespawnRecursiveExt, Entity, Input, KeyCode, Local,
+    Query, Res, ResMut, Transform, Vec3, With,
+};
+use bevy::sprite::{Sprite, SpriteBundle};
+use bevy::time::{Time, Timer};
+
+use crate::{BoardLayout, FallingSegment, Tile, Z_TRAIL};
+
+/// How long a trail mark takes to fade away
+const TRAIL_FADE: Duration = Duration::from_millis(600);
+const TRAIL: Color = Color::rgba(1.0, 1.0, 1.0, 0.3);
+
+/// Whether to leave a fading trail behind the falling piece, for studying its path.
+/// Toggled with T.
+pub(crate) struct MoveTrail(pub bool);
+
+/// A cell the falling piece was in
+#[derive(Component)]
+pub(crate) struct TrailMark {
+    cell: Tile,
+    timer: Timer,
+}
+
+pub(crate) fn toggle_move_trail(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut move_trail: ResMut<MoveTrail>,
+) {
+    if keyboard_input.just_pressed(KeyCode::T) {
+        move_trail.0 = !move_trail.0;
+    }
+}
+
+fn trail_transform(layout: &BoardLayout, cell: Tile) -> Transform {
+    Transform {
+        translation: layout
+            .cell_position(f32::from(cell.x), f32::from(cell.y))
+            .extend(Z_TRAIL),
+        scale: Vec3::new(layout.cell_size(), layout.cell_size(), 1.0),
+        ..Default::default()
+    }
+}
+
+pub(crate) fn record_move_trail(
+    move_trail: Res<MoveTrail>,
+    layout: Res<BoardLayout>,
+    segments: Query<&Tile, With<FallingSegment>>,
+    moved: Query<(), (With<FallingSegment>, Changed<Tile>)>,
+    spawned: Query<(), Added<FallingSegment>>,
+    marks: Query<Entity, With<TrailMark>>,
+    mut last_position: Local<Vec<Tile>>,
+    mut commands: Commands,
+) {
+    let position: Vec<Tile> = segments.iter().copied().collect();
+    if !move_trail.0 || position.is_empty() || !spawned.is_empty() {
+        // Off, or a piece locked or spawned, so start afresh
+        marks.for_each(|entity| commands.entity(entity).despawn_recursive());
+    } else if !moved.is_empty() && position != *last_position {
+        for &cell in last_position.iter() {
+            commands
+                .spawn_bundle(SpriteBundle {
+                    sprite: Sprite {
+                        color: TRAIL,
+                        ..Default::default()
+                    },
+                    transform: trail_transform(&layout, cell),
+                    ..Default::default()
+                })
+                .insert(TrailMark {
+                    cell,
+                    timer: Timer::new(TRAIL_FADE, false),
+                });
+        }
+    }
+    *last_position = position;
+}
+
+pub(crate) fn fade_move_trail(
+    time: Res<Time>,
+    layout: Res<BoardLayout>,
+    mut marks: Query<(Entity, &mut Transform, &mut Sprite, &mut TrailMark)>,
+    mut commands: Commands,
+) {
+    for (entity, mut transform, mut sprite, mut mark) in &mut marks {
+        if mark.timer.tick(time.delta()).finished() {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+        if layout.is_changed() {
+            *transform = trail_transform(&layout, mark.cell);
+        }
+        sprite.color.set_a(TRAIL.a() * (1.0 - mark.timer.percent()));
+    }
+}
@@ -0,0 +1,67 @@
+use bevy::log::warn;
+use bevy::prelude::{NonSend, Res, ResMut, State};
+use bevy::window::{WindowId, Windows};
+use bevy::winit::WinitWindows;
+use winit::window::Icon;
+
+use crate::{GameMode, GameState, Score};
+
+/// The icon's width and height in pixels
+const ICON_SIZE: usize = 32;
+const ICON_CELL: usize = 10;
+/// The cells of the T piece drawn on the icon, from the top left
+const ICON_CELLS: [(usize, usize); 4] = [(0, 0), (1, 0), (2, 0), (1, 1)];
+const ICON_COLOR: [u8; 4] = [0xa0, 0x20, 0xf0, 0xff];
+
+/// Draws a T piece, each cell outlined so the blocks stand apart
+fn icon_pixels() -> Vec<u8> {
+    let mut rgba = vec![0; ICON_SIZE * ICON_SIZE * 4];
+    // Centers the three-by-two piece
+    let (left, top) = (1, 6);
+    for (cell_x, cell_y) in ICON_CELLS {
+        for dy in 1..ICON_CELL - 1 {
+            for dx in 1..ICON_CELL - 1 {
+                let x = left + cell_x * ICON_CELL + dx;
+                let y = top + cell_y * ICON_CELL + dy;
+                let i = (y * ICON_SIZE + x) * 4;
+                rgba[i..i + 4].copy_from_slice(&ICON_COLOR);
+            }
+        }
+    }
+    rgba
+}
+
+pub(crate) fn set_window_icon(windows: NonSend<WinitWindows>) {
+    let window = match windows.get_window(WindowId::primary()) {
+        Some(window) => window,
+        None => return,
+    };
+    match Icon::from_rgba(icon_pixels(), ICON_SIZE as u32, ICON_SIZE as u32) {
+        Ok(icon) => window.set_window_icon(Some(icon)),
+        Err(err) => warn!("Couldn't make the window icon: {}", err),
+    }
+}
+
+/// Shows the mode and score in the title bar
+pub(crate) fn update_window_title(
+    mode: Res<GameMode>,
+    score: Option<Res<Score>>,
+    game_state: Res<State<GameState>>,
+    mut windows: ResMut<Windows>,
+) {
+    let score = match score {
+        Some(score) => score.0,
+        None => return,
+    };
+    let title = match game_state.current() {
+        GameState::Playing => format!("Tetris — {} — Score: {}", mode.name(), score),
+        GameState::GameOver | GameState::GameOverAnimation => {
+            format!("Tetris — {} — Game over, score: {}", mode.name(), score)
+        }
+    };
+    if let Some(window) = windows.get_primary_mut() {
+        if window.title() != title {
+            window.set_title(title);
+        }
+    }
+}
@@ -0,0 +1,50 @@
+use bevy::prelude::{
+    Color, Commands, Component, DespawnRecursiveExt, Entity, Query, Res, Transform, Vec3, With,
+};
+use bevy::sprite::{Sprite, SpriteBundle};
+
+use crate::{BoardLayout, COLUMNS, ROWS, Z_DANGER_LINE};
+
+const WRAP_INDICATOR: Color = Color::rgba(0.3, 0.8, 1.0, 0.6);
+/// The thickness of the edge bars, in pixels
+const WRAP_INDICATOR_WIDTH: f32 = 3.0;
+
+/// An experimental variant where moving past one side of the board comes back in on the other.
+/// Enabled by setting the `TETRIS_WRAP` environment variable.
+pub(crate) struct WrapMode(pub bool);
+
+/// Marks the board's side edges as open while wrap mode is on
+#[derive(Component)]
+pub(crate) struct WrapIndicator;
+
+pub(crate) fn update_wrap_indicator(
+    layout: Res<BoardLayout>,
+    wrap_mode: Res<WrapMode>,
+    indicators: Query<Entity, With<WrapIndicator>>,
+    mut commands: Commands,
+) {
+    if !layout.is_changed() && !wrap_mode.is_changed() {
+        return;
+    }
+    indicators.for_each(|entity| commands.entity(entity).despawn_recursive());
+    if !wrap_mode.0 {
+        return;
+    }
+    for x in [-0.5, COLUMNS as f32 - 0.5] {
+        let position = layout.cell_position(x, (ROWS - 1) as f32 / 2.0);
+        commands
+            .spawn_bundle(SpriteBundle {
+                sprite: Sprite {
+                    color: WRAP_INDICATOR,
+                    ..Default::default()
+                },
+                transform: Transform {
+                    translation: position.extend(Z_DANGER_LINE),
+                    scale: Vec3::new(WRAP_INDICATOR_WIDTH, ROWS as f32 * layout.cell_size(), 1.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .insert(WrapIndicator);
+    }
+}